@@ -0,0 +1,399 @@
+// Headless renderer, bounces .cut files to WAV without the GUI.
+//
+// cut_render [OPTIONS] <PATH>...
+//
+// PATH is a .cut file or a directory, in which case all .cut files in it are rendered.
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use cut_creator::{
+    model::Cut,
+    render::{BitDepth, RenderSettings},
+    sinc::{self, Quality},
+};
+use log::{debug, info};
+
+const USAGE: &str = "\
+Usage: cut_render [OPTIONS] <PATH>...
+
+Render .cut files to WAV. PATH is a .cut file or a directory of .cut files.
+
+Options:
+  -t, --tempo <BPM>       tempo in quarter notes per minute
+  -r, --rate <HZ>         output sample rate
+  -b, --bars <BARS>       render length in bars, overriding the cut length
+  -f, --format <FORMAT>   output format: int16, int24 or float32
+  -q, --quality <LEVEL>   resampling quality: draft, normal, high or best
+  -o, --output <PATH>     output file of a single cut, otherwise a directory
+                          (created if missing)
+  -h, --help              print this help
+";
+
+/// Command line overrides of the render settings stored in each cut
+#[derive(Default, Debug)]
+struct Args {
+    help: bool,
+    tempo: Option<f32>,
+    rate: Option<u32>,
+    bars: Option<f32>,
+    format: Option<BitDepth>,
+    quality: Option<Quality>,
+    output: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("Missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+/// Parse a value above 0
+fn parse_positive<T>(flag: &str, value: Option<String>) -> Result<T, String>
+where
+    T: std::str::FromStr + PartialOrd + Default + Into<f64> + Copy,
+{
+    let parsed: T = parse(flag, value)?;
+    if !(parsed > T::default() && parsed.into().is_finite()) {
+        Err(format!(
+            "Invalid value for {}: {}, must be above 0\n\n{}",
+            flag,
+            parsed.into(),
+            USAGE
+        ))?;
+    }
+    Ok(parsed)
+}
+
+fn parse_args(mut iter: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = Args::default();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                args.help = true;
+                return Ok(args);
+            }
+            "-t" | "--tempo" => args.tempo = Some(parse_positive(&arg, iter.next())?),
+            "-r" | "--rate" => args.rate = Some(parse_positive(&arg, iter.next())?),
+            "-b" | "--bars" => args.bars = Some(parse_positive(&arg, iter.next())?),
+            "-o" | "--output" => args.output = Some(parse(&arg, iter.next())?),
+            "-f" | "--format" => {
+                let value: String = parse(&arg, iter.next())?;
+                args.format = Some(match value.as_str() {
+                    "int16" => BitDepth::Int16,
+                    "int24" => BitDepth::Int24,
+                    "float32" => BitDepth::Float32,
+                    _ => Err(format!("Unknown format {}", value))?,
+                });
+            }
+            "-q" | "--quality" => {
+                let value: String = parse(&arg, iter.next())?;
+                args.quality = Some(
+                    *Quality::ALL
+                        .iter()
+                        .find(|quality| quality.name() == value)
+                        .ok_or(format!("Unknown quality {}", value))?,
+                );
+            }
+            _ if arg.starts_with('-') => Err(format!("Unknown option {}\n\n{}", arg, USAGE))?,
+            _ => args.paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if args.paths.is_empty() {
+        Err(USAGE.to_string())?;
+    }
+    Ok(args)
+}
+
+/// Expand directories to the .cut files they contain
+fn cut_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut cut_paths = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let entries = std::fs::read_dir(path).map_err(|err| format!("{:?}", err))?;
+            let mut cuts: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "cut"))
+                .collect();
+            cuts.sort();
+            cut_paths.extend(cuts);
+        } else {
+            cut_paths.push(path.clone());
+        }
+    }
+    Ok(cut_paths)
+}
+
+/// Destination of the renders
+#[derive(Debug, PartialEq)]
+enum Output {
+    /// As stored in each cut, or next to it
+    Cut,
+    /// The single cut to this file
+    File(PathBuf),
+    /// Each cut into this directory, by its output file name
+    Dir(PathBuf),
+}
+
+/// Output given by -o: a file if one cut file is rendered and the path is
+/// not a directory, otherwise a directory
+fn output(args: &Args) -> Result<Output, String> {
+    let Some(output) = &args.output else {
+        return Ok(Output::Cut);
+    };
+    let single = args.paths.len() == 1 && !args.paths[0].is_dir();
+    if single && !output.is_dir() {
+        Ok(Output::File(output.clone()))
+    } else if output.exists() && !output.is_dir() {
+        Err(format!(
+            "Output {} is not a directory, required when rendering several cuts",
+            output.display()
+        ))
+    } else {
+        Ok(Output::Dir(output.clone()))
+    }
+}
+
+/// Settings of the cut with command line overrides applied
+fn settings(args: &Args, cut: &Cut, output: &Output) -> RenderSettings {
+    let mut settings = cut.render.clone();
+    if let Some(tempo) = args.tempo {
+        settings.bpm = tempo;
+    }
+    if let Some(rate) = args.rate {
+        settings.sample_rate = rate;
+    }
+    if let Some(format) = args.format {
+        settings.bit_depth = format;
+    }
+    if let Some(quality) = args.quality {
+        settings.quality = quality;
+    }
+    if args.bars.is_some() {
+        settings.bars = args.bars;
+    }
+    match output {
+        Output::Cut => {}
+        Output::File(path) => settings.path = Some(path.clone()),
+        Output::Dir(dir) => {
+            let path = settings.output_path(&cut.cut_path);
+            settings.path = path.file_name().map(|name| dir.join(name));
+        }
+    }
+    settings
+}
+
+/// Load the cut and its settings, the sample must be found
+fn load(args: &Args, path: &Path, output: &Output) -> Result<(Cut, RenderSettings), String> {
+    let (cut, _) = Cut::load_path(path.to_path_buf(), None).map_err(|err| err.to_string())?;
+    if cut.sample_missing() {
+        let sample_path = cut.sample_path.clone().unwrap_or_default();
+        Err(format!("Sample not found: {}", sample_path.display()))?;
+    }
+    let settings = settings(args, &cut, output);
+    debug!("settings {:?}", settings);
+    Ok((cut, settings))
+}
+
+/// Error if two inputs render to the same file, e.g. cuts of the same name
+/// from different directories into one output directory
+fn check_clashes(outputs: &[(&Path, PathBuf)]) -> Result<(), String> {
+    for (i, (path, output)) in outputs.iter().enumerate() {
+        if let Some((other, _)) = outputs[..i].iter().find(|(_, other)| other == output) {
+            Err(format!(
+                "{} and {} both render to {}",
+                other.display(),
+                path.display(),
+                output.display()
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    info!("env_logger started");
+
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return ExitCode::FAILURE;
+        }
+    };
+    if args.help {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let inputs = cut_paths(&args.paths).and_then(|paths| Ok((paths, output(&args)?)));
+    let (paths, output) = match inputs {
+        Ok(inputs) => inputs,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // load all cuts first, nothing is written if two of them clash
+    let mut failed = false;
+    let mut cuts = vec![];
+    for path in &paths {
+        match load(&args, path, &output) {
+            Ok((cut, settings)) => cuts.push((path.as_path(), cut, settings)),
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+    let outputs: Vec<(&Path, PathBuf)> = cuts
+        .iter()
+        .map(|(path, cut, settings)| (*path, settings.output_path(&cut.cut_path)))
+        .collect();
+    if let Err(msg) = check_clashes(&outputs) {
+        eprintln!("{}", msg);
+        return ExitCode::FAILURE;
+    }
+    if let (Output::Dir(dir), false) = (&output, cuts.is_empty()) {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            eprintln!("{}: {}", dir.display(), err);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    for (path, cut, settings) in &cuts {
+        match sinc::sinc_resample(cut, settings) {
+            Ok(out) => println!("{} -> {}", path.display(), out.display()),
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    /// Empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cut_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn positive() {
+        let value = |value: &str| Some(value.to_string());
+        assert_eq!(parse_positive::<u32>("-r", value("44100")), Ok(44100));
+        assert_eq!(parse_positive::<f32>("-t", value("95.5")), Ok(95.5));
+        assert!(parse_positive::<u32>("-r", value("0")).is_err());
+        assert!(parse_positive::<u32>("-r", value("-1")).is_err());
+        assert!(parse_positive::<f32>("-b", value("0.0")).is_err());
+        assert!(parse_positive::<f32>("-b", value("-2")).is_err());
+        assert!(parse_positive::<f32>("-b", value("inf")).is_err());
+        assert!(parse_positive::<f32>("-b", value("NaN")).is_err());
+        assert!(parse_positive::<f32>("-t", value("fast")).is_err());
+        assert_eq!(
+            parse_positive::<f32>("-t", None),
+            Err("Missing value for -t".to_string())
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let parsed = args(&["-t", "95", "-f", "float32", "-o", "out", "a.cut", "b"]).unwrap();
+        assert_eq!(parsed.tempo, Some(95.0));
+        assert_eq!(parsed.format, Some(BitDepth::Float32));
+        assert_eq!(parsed.output, Some(PathBuf::from("out")));
+        assert_eq!(parsed.paths, [PathBuf::from("a.cut"), PathBuf::from("b")]);
+
+        assert!(args(&["-h"]).unwrap().help);
+        assert!(args(&[]).is_err());
+        assert!(args(&["-x", "a.cut"]).is_err());
+        assert!(args(&["-r", "0", "a.cut"]).is_err());
+        assert!(args(&["a.cut", "-o"]).is_err());
+    }
+
+    #[test]
+    fn output_paths() {
+        let dir = test_dir("render_output");
+        let file = dir.join("file.wav");
+        std::fs::write(&file, []).unwrap();
+        let output = |paths: &[&Path], out: &Path| {
+            output(&Args {
+                paths: paths.iter().map(|path| path.to_path_buf()).collect(),
+                output: Some(out.to_path_buf()),
+                ..Args::default()
+            })
+        };
+        let (a, b) = (Path::new("a.cut"), Path::new("b.cut"));
+        let new = dir.join("new");
+
+        // a single cut to a file, or into an existing directory
+        assert_eq!(output(&[a], &new), Ok(Output::File(new.clone())));
+        assert_eq!(output(&[a], &file), Ok(Output::File(file.clone())));
+        assert_eq!(output(&[a], &dir), Ok(Output::Dir(dir.clone())));
+        // several cuts, or a directory of cuts, into a directory
+        assert_eq!(output(&[a, b], &new), Ok(Output::Dir(new.clone())));
+        assert_eq!(output(&[&dir], &new), Ok(Output::Dir(new.clone())));
+        assert!(output(&[a, b], &file).is_err());
+        assert!(output(&[&dir], &file).is_err());
+        assert_eq!(super::output(&Args::default()), Ok(Output::Cut));
+
+        // the output file name of each cut
+        let mut cut = Cut::default();
+        cut.cut_path = PathBuf::from("cuts/a.cut");
+        let path = |cut: &Cut, output: &Output| settings(&Args::default(), cut, output).path;
+        assert_eq!(path(&cut, &Output::Cut), None);
+        assert_eq!(path(&cut, &Output::File(file.clone())), Some(file.clone()));
+        assert_eq!(
+            path(&cut, &Output::Dir(new.clone())),
+            Some(new.join("a_render.wav"))
+        );
+        cut.render.path = Some(PathBuf::from("renders/ahh.wav"));
+        assert_eq!(
+            path(&cut, &Output::Cut),
+            Some(PathBuf::from("renders/ahh.wav"))
+        );
+        assert_eq!(
+            path(&cut, &Output::Dir(new.clone())),
+            Some(new.join("ahh.wav"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clashes() {
+        let (a, b) = (Path::new("x/a.cut"), Path::new("y/a.cut"));
+        let out = |name: &str| Path::new("out").join(name);
+        assert!(check_clashes(&[(a, out("a_render.wav")), (b, out("b_render.wav"))]).is_ok());
+        assert_eq!(
+            check_clashes(&[(a, out("a_render.wav")), (b, out("a_render.wav"))]),
+            Err(format!(
+                "x/a.cut and y/a.cut both render to {}",
+                out("a_render.wav").display()
+            ))
+        );
+    }
+}
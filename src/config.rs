@@ -1,10 +1,65 @@
-use egui::{Color32, Stroke};
+use egui::{Color32, ComboBox, DragValue, Grid, Slider, Stroke, Ui, Visuals};
 /// config
 ///
+/// Read from `config.json` in the working directory if there is one, otherwise
+/// from the platform config directory. Missing fields take their default.
+use log::debug;
 use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-#[derive(Deserialize, Serialize)]
+/// Name of the config file
+const CONFIG_FILE: &str = "config.json";
+
+/// Directory of the app in the platform config directory
+const APP_DIR: &str = "cut_creator";
+
+/// Colour theme, a preset of the strokes and the egui visuals
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::HighContrast => "high contrast",
+        }
+    }
+
+    /// egui visuals of the theme
+    pub fn visuals(&self) -> Visuals {
+        match self {
+            Theme::Dark => Visuals::dark(),
+            Theme::Light => Visuals::light(),
+            Theme::HighContrast => {
+                let mut visuals = Visuals::dark();
+                visuals.override_text_color = Some(Color32::WHITE);
+                visuals.panel_fill = Color32::BLACK;
+                visuals.window_fill = Color32::BLACK;
+                visuals.extreme_bg_color = Color32::BLACK;
+                visuals
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
+    /// Theme the strokes are based on
+    pub theme: Theme,
+
     /// Stroke selected.
     pub stroke_select: Stroke,
 
@@ -35,13 +90,23 @@ pub struct Config {
     /// Strake fader
     pub stroke_fader: Stroke,
 
-    /// Fader height, in pixels
+    /// Height of the fader lane below the cut, in pixels
     pub fader_height: f32,
+
+    /// Project sample rate, samples are converted to it on load
+    pub sample_rate: u32,
+
+    /// Stroke playhead
+    pub stroke_playhead: Stroke,
+
+    /// Session restored on startup
+    pub last_session: Option<PathBuf>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            theme: Theme::Dark,
             stroke_select: Stroke::new(1.0, Color32::WHITE.linear_multiply(0.25)),
             stroke_knot: Stroke::new(1.0, Color32::WHITE.linear_multiply(0.25)),
             stroke_knot_selected: Stroke::new(1.0, Color32::WHITE),
@@ -53,6 +118,192 @@ impl Default for Config {
             stroke_sample: Stroke::new(1.0, Color32::GREEN.linear_multiply(0.25)),
             stroke_fader: Stroke::new(1.0, Color32::YELLOW.linear_multiply(1.0)),
             fader_height: 30.0,
+            sample_rate: 48000,
+            stroke_playhead: Stroke::new(1.0, Color32::WHITE),
+            last_session: None,
+        }
+    }
+}
+
+impl Config {
+    /// Defaults with the strokes and colours of theme
+    fn themed(theme: Theme) -> Config {
+        let config = Config::default();
+        match theme {
+            Theme::Dark => config,
+            Theme::Light => Config {
+                theme,
+                stroke_select: Stroke::new(1.0, Color32::BLACK.linear_multiply(0.25)),
+                stroke_knot: Stroke::new(1.0, Color32::DARK_GRAY),
+                stroke_knot_selected: Stroke::new(1.0, Color32::BLACK),
+                stroke_line: Stroke::new(1.0, Color32::DARK_RED.linear_multiply(0.5)),
+                grid_color: Color32::DARK_GRAY,
+                stroke_spline: Stroke::new(1.0, Color32::DARK_BLUE),
+                stroke_sample: Stroke::new(1.0, Color32::DARK_GREEN.linear_multiply(0.5)),
+                stroke_fader: Stroke::new(1.0, Color32::from_rgb(0xc0, 0x80, 0x00)),
+                stroke_playhead: Stroke::new(1.0, Color32::BLACK),
+                ..config
+            },
+            Theme::HighContrast => Config {
+                theme,
+                stroke_select: Stroke::new(1.0, Color32::WHITE),
+                stroke_knot: Stroke::new(2.0, Color32::LIGHT_GRAY),
+                stroke_knot_selected: Stroke::new(2.0, Color32::YELLOW),
+                stroke_line: Stroke::new(1.0, Color32::RED),
+                grid_color: Color32::WHITE,
+                stroke_spline: Stroke::new(2.0, Color32::from_rgb(0x40, 0xc0, 0xff)),
+                stroke_sample: Stroke::new(1.0, Color32::GREEN),
+                stroke_fader: Stroke::new(2.0, Color32::YELLOW),
+                stroke_playhead: Stroke::new(2.0, Color32::WHITE),
+                ..config
+            },
+        }
+    }
+
+    /// Set the theme, replacing the strokes and colours. Sizes and the
+    /// sample rate are kept.
+    pub fn set_theme(&mut self, theme: Theme) {
+        *self = Config {
+            knot_line: self.knot_line,
+            step_size: self.step_size,
+            fader_height: self.fader_height,
+            sample_rate: self.sample_rate,
+            last_session: self.last_session.take(),
+            ..Config::themed(theme)
+        };
+    }
+
+    /// Reset the settings to their defaults, keeps the sample rate and session
+    pub fn reset(&mut self) {
+        *self = Config {
+            sample_rate: self.sample_rate,
+            last_session: self.last_session.take(),
+            ..Config::default()
+        };
+    }
+
+    /// Settings window, changes apply immediately. Returns true if any setting changed.
+    pub fn ui_content(&mut self, ui: &mut Ui) -> bool {
+        let old = self.clone();
+
+        let mut theme = self.theme;
+        ui.horizontal(|ui| {
+            ui.label("Theme");
+            ComboBox::from_id_source("config_theme")
+                .selected_text(theme.name())
+                .show_ui(ui, |ui| {
+                    for t in Theme::ALL {
+                        ui.selectable_value(&mut theme, t, t.name());
+                    }
+                });
+        });
+        if theme != self.theme {
+            self.set_theme(theme);
+        }
+        ui.add_space(10.0);
+
+        Grid::new("config_settings")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Knot lines");
+                ui.checkbox(&mut self.knot_line, "");
+                ui.end_row();
+
+                ui.label("Step size");
+                ui.add(DragValue::new(&mut self.step_size).range(1..=100));
+                ui.end_row();
+
+                ui.label("Fader height");
+                ui.add(Slider::new(&mut self.fader_height, 10.0..=100.0).suffix(" px"));
+                ui.end_row();
+
+                for (name, stroke) in [
+                    ("Select", &mut self.stroke_select),
+                    ("Knot", &mut self.stroke_knot),
+                    ("Knot selected", &mut self.stroke_knot_selected),
+                    ("Lines", &mut self.stroke_line),
+                    ("Spline", &mut self.stroke_spline),
+                    ("Sample", &mut self.stroke_sample),
+                    ("Fader", &mut self.stroke_fader),
+                    ("Playhead", &mut self.stroke_playhead),
+                ] {
+                    ui.label(name);
+                    ui.add(Slider::new(&mut stroke.width, 0.5..=5.0).suffix(" px"));
+                    ui.color_edit_button_srgba(&mut stroke.color);
+                    ui.end_row();
+                }
+
+                ui.label("Grid");
+                ui.label("");
+                ui.color_edit_button_srgba(&mut self.grid_color);
+                ui.end_row();
+            });
+
+        ui.add_space(10.0);
+        if ui.button("Reset to defaults").clicked() {
+            self.reset();
+        }
+
+        *self != old
+    }
+
+    /// Load the config, a missing or empty file gives the defaults
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => Err(format!("Could not read {}: {}", path.display(), err))?,
+        };
+        debug!("config json {}", json);
+        if json.trim().is_empty() {
+            return Ok(Config::default());
+        }
+        serde_json::from_str(&json).map_err(|err| format!("Malformed {}: {}", path.display(), err))
+    }
+
+    /// Save the config, written to a temporary file first and then renamed over
+    /// the config, so an interrupted write leaves the old config intact
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|err| format!("{:?}", err))?;
+        debug!("json config {}", json);
+
+        let error = |err: std::io::Error| format!("Could not write {}: {}", path.display(), err);
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(error)?;
         }
+        let tmp_path = path.with_extension("json.tmp");
+        let mut file = File::create(&tmp_path).map_err(error)?;
+        file.write_all(json.as_bytes()).map_err(error)?;
+        file.sync_all().map_err(error)?;
+        std::fs::rename(&tmp_path, path).map_err(error)
+    }
+}
+
+/// Config directory of the app: `$XDG_CONFIG_HOME` or `~/.config` on Linux,
+/// `~/Library/Application Support` on macOS and `%APPDATA%` on Windows
+pub fn config_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| {
+        std::env::var_os(name)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+    let dir = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    };
+    dir.map(|dir| dir.join(APP_DIR))
+}
+
+/// Path of the config, `config.json` in the working directory overrides the
+/// one in the config directory
+pub fn config_path() -> PathBuf {
+    let local = PathBuf::from(CONFIG_FILE);
+    if local.exists() {
+        return local;
     }
+    config_dir().map_or(local, |dir| dir.join(CONFIG_FILE))
 }
@@ -0,0 +1,507 @@
+// cut file format
+//
+// A .cut file is the JSON serialization of a `model::Cut`, with a `version` field.
+// Files are migrated to the current version before deserialization, and
+// validated after.
+//
+// Versions:
+// 0: initial format, no version field
+// 1: version field, render settings
+// 2: sample path relative to the cut file, sample size and hash for relinking
+// 3: channel pair of multichannel samples
+// 4: sample rate of the wav window
+// 5: swing of the grid
+// 6: interpolation per cut knot
+// 7: monotone cubic option
+// 8: tangent handles on cut knots
+// 9: fader curve and cut-in lag
+use crate::model::Cut;
+use serde_json::Value;
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use log::debug;
+
+/// Current format version
+pub const VERSION: u32 = 9;
+
+/// Errors loading a cut
+#[derive(Debug)]
+pub enum CutError {
+    /// File could not be read
+    Io(std::io::Error),
+    /// File is not valid JSON, or does not match the format
+    Parse(serde_json::Error),
+    /// File is newer than this version of the application
+    UnsupportedVersion(u32),
+    /// File type is neither .cut nor a supported sample
+    UnsupportedType(String),
+    /// The referenced sample could not be loaded
+    Sample(String),
+    /// A knot lane has too few knots
+    TooFewKnots {
+        lane: &'static str,
+        len: usize,
+        min: usize,
+    },
+    /// Knot x positions are not increasing
+    NonMonotonic { lane: &'static str, index: usize },
+    /// The length of the cut is not positive
+    InvalidBars(f32),
+    /// Quantization is zero
+    InvalidQuantization(u32),
+    /// Render settings give no render, e.g. tempo or sample rate 0
+    InvalidRender(String),
+    /// Fader curve exponent is not positive
+    InvalidFaderCurve(f32),
+    /// Cut-in lag outside 0.0..=0.5
+    InvalidFaderLag(f32),
+}
+
+impl fmt::Display for CutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CutError::Io(err) => write!(f, "Could not read file: {}", err),
+            CutError::Parse(err) => write!(f, "Malformed cut file: {}", err),
+            CutError::UnsupportedVersion(version) => write!(
+                f,
+                "Cut file version {} is newer than supported ({})",
+                version, VERSION
+            ),
+            CutError::UnsupportedType(ext) => write!(f, "Filetype not supported: {}", ext),
+            CutError::Sample(err) => write!(f, "Could not load sample: {}", err),
+            CutError::TooFewKnots { lane, len, min } => write!(
+                f,
+                "Too few {} knots: {}, at least {} required",
+                lane, len, min
+            ),
+            CutError::NonMonotonic { lane, index } => {
+                write!(f, "{} knot #{} is out of order", lane, index)
+            }
+            CutError::InvalidBars(bars) => write!(f, "Invalid length: {} bars", bars),
+            CutError::InvalidQuantization(q) => write!(f, "Invalid quantization: {}", q),
+            CutError::InvalidRender(err) => write!(f, "Invalid render settings: {}", err),
+            CutError::InvalidFaderCurve(exponent) => {
+                write!(f, "Invalid fader curve exponent: {}", exponent)
+            }
+            CutError::InvalidFaderLag(lag) => write!(f, "Invalid cut-in lag: {}", lag),
+        }
+    }
+}
+
+impl std::error::Error for CutError {}
+
+impl From<std::io::Error> for CutError {
+    fn from(err: std::io::Error) -> Self {
+        CutError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for CutError {
+    fn from(err: serde_json::Error) -> Self {
+        CutError::Parse(err)
+    }
+}
+
+/// Version of a parsed cut file, files without version are version 0
+fn version(value: &Value) -> u32 {
+    value
+        .get("version")
+        .and_then(Value::as_u64)
+        .map_or(0, |version| version as u32)
+}
+
+/// Migrate a parsed cut file to the current version
+pub fn migrate(mut value: Value) -> Result<Value, CutError> {
+    let version = version(&value);
+    if version > VERSION {
+        Err(CutError::UnsupportedVersion(version))?;
+    }
+
+    // All versions so far only add fields, the serde defaults give the old
+    // behaviour: default render settings (1), absolute sample paths resolve as
+    // is and size and hash are unknown (2), the first two channels (3), the
+    // window in samples of the file (4), a straight grid (5), Catmull-Rom knots
+    // (6, 7) without handles (8), a linear fader without lag (9). A version
+    // changing the meaning of a field transforms value here.
+    if version < VERSION {
+        debug!("migrate cut from version {}", version);
+    }
+
+    if let Value::Object(map) = &mut value {
+        map.insert("version".to_string(), Value::from(VERSION));
+    }
+    Ok(value)
+}
+
+/// Parse, migrate and validate a cut, splines are not updated
+pub fn from_json(json: &str) -> Result<Cut, CutError> {
+    let value: Value = serde_json::from_str(json)?;
+    let cut: Cut = serde_json::from_value(migrate(value)?)?;
+    cut.validate()?;
+    Ok(cut)
+}
+
+/// Serialize a cut, the sample path is stored relative to the cut file when possible
+pub fn to_json(cut: &Cut) -> Result<String, CutError> {
+    let mut value = serde_json::to_value(cut)?;
+    if let (Some(sample_path), Some(cut_dir)) = (&cut.sample_path, cut.cut_path.parent()) {
+        if let Some(relative) = relative_path(cut_dir, sample_path) {
+            value["sample_path"] = serde_json::to_value(relative)?;
+        }
+    }
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Path to `path` relative to the directory `dir`, None if they share no root.
+/// Relative paths are taken from the working directory.
+pub fn relative_path(dir: &Path, path: &Path) -> Option<PathBuf> {
+    // the parent of a bare file name is empty
+    let absolute = |path: &Path| match path.as_os_str().is_empty() {
+        true => std::path::absolute("."),
+        false => std::path::absolute(path),
+    };
+    let dir = normalize(&absolute(dir).ok()?);
+    let path = normalize(&absolute(path).ok()?);
+    let dir: Vec<Component> = dir.components().collect();
+    let path: Vec<Component> = path.components().collect();
+
+    let common = dir.iter().zip(&path).take_while(|(a, b)| a == b).count();
+    // must at least share the prefix/root (e.g. the same drive)
+    if common == 0 || matches!(path.get(common - 1), Some(Component::Prefix(_))) {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..dir.len() {
+        relative.push("..");
+    }
+    for component in &path[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+/// Resolve a stored sample path against the directory of the cut
+pub fn resolve_path(cut_dir: &Path, sample_path: &Path) -> PathBuf {
+    normalize(&cut_dir.join(sample_path))
+}
+
+/// Remove `.` and resolve `..` lexically, a leading `..` of a relative path is kept
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal: Vec<Component> = vec![];
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.last() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                // the parent of the root is the root
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normal.push(component),
+            },
+            component => normal.push(component),
+        }
+    }
+    normal.iter().collect()
+}
+
+/// File name of a stored path, which may come from another platform
+pub fn file_name(path: &Path) -> Option<String> {
+    path.to_string_lossy()
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+}
+
+/// Size and hash of a sample file, used to recognize it when relinking
+pub fn file_info(path: &Path) -> Option<(u64, u64)> {
+    let bytes = std::fs::read(path).ok()?;
+    // FNV-1a
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    Some((bytes.len() as u64, hash))
+}
+
+/// Directories searched for a missing sample: the cut directory and its parent,
+/// with their sub directories down to `depth`
+fn search_dirs(cut_dir: &Path, depth: usize) -> Vec<PathBuf> {
+    fn walk(dir: &Path, depth: usize, dirs: &mut Vec<PathBuf>) {
+        if dirs.iter().any(|d| d == dir) {
+            return;
+        }
+        dirs.push(dir.to_path_buf());
+        if depth == 0 {
+            return;
+        }
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            let mut sub_dirs: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_dir())
+                .collect();
+            sub_dirs.sort();
+            for sub_dir in sub_dirs {
+                walk(&sub_dir, depth - 1, dirs);
+            }
+        }
+    }
+
+    let mut dirs = vec![];
+    walk(cut_dir, depth, &mut dirs);
+    if let Some(parent) = cut_dir.parent() {
+        walk(parent, depth, &mut dirs);
+    }
+    dirs
+}
+
+/// Candidates for a missing sample, best first, and whether the first is a sure match.
+///
+/// Files with the same size and hash as recorded are sure matches, files with the
+/// same name are candidates. Without recorded size and hash a single file with
+/// the same name is taken as a sure match.
+pub fn relink_candidates(
+    cut_dir: &Path,
+    sample_path: &Path,
+    info: Option<(u64, u64)>,
+) -> (Vec<PathBuf>, bool) {
+    let name = file_name(sample_path);
+    let ext = Path::new(name.as_deref().unwrap_or_default())
+        .extension()
+        .map(|ext| ext.to_ascii_lowercase());
+
+    let mut same_hash = vec![];
+    let mut same_name = vec![];
+    for dir in search_dirs(cut_dir, 2) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        files.sort();
+
+        for file in files {
+            let is_same_name = file_name(&file) == name;
+            let is_same_ext = file.extension().map(|ext| ext.to_ascii_lowercase()) == ext;
+            match info {
+                // renamed files are found by size and hash
+                Some((size, hash)) if is_same_name || is_same_ext => {
+                    let same_size = std::fs::metadata(&file).is_ok_and(|m| m.len() == size);
+                    if same_size && file_info(&file) == Some((size, hash)) {
+                        same_hash.push(file);
+                    } else if is_same_name {
+                        same_name.push(file);
+                    }
+                }
+                _ if is_same_name => same_name.push(file),
+                _ => {}
+            }
+        }
+    }
+    debug!(
+        "relink same hash {:?}, same name {:?}",
+        same_hash, same_name
+    );
+
+    let sure = match info {
+        Some(_) => same_hash.len() == 1,
+        None => same_name.len() == 1,
+    };
+    same_hash.extend(same_name);
+    (same_hash, sure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::FaderCurve, render::RenderSettings};
+
+    /// A version 0 file, without version field
+    const VERSION_0: &str = include_str!("../audio/ahh.cut");
+
+    #[test]
+    fn migrate_version_0() {
+        let value: Value = serde_json::from_str(VERSION_0).unwrap();
+        assert_eq!(version(&value), 0);
+        assert_eq!(version(&migrate(value).unwrap()), VERSION);
+
+        let cut = from_json(VERSION_0).unwrap();
+        assert_eq!(cut.get_bars(), 2.0);
+        assert_eq!(cut.get_swing(), 50.0);
+        assert!(!cut.is_monotone());
+        assert_eq!(cut.get_fader_curve(), (FaderCurve::Linear, 0.0));
+        assert_eq!(cut.render, RenderSettings::default());
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = to_json(&from_json(VERSION_0).unwrap()).unwrap();
+        assert_eq!(to_json(&from_json(&json).unwrap()).unwrap(), json);
+    }
+
+    #[test]
+    fn newer_version() {
+        let mut value: Value = serde_json::from_str(VERSION_0).unwrap();
+        value["version"] = Value::from(VERSION + 1);
+        assert!(matches!(
+            from_json(&value.to_string()),
+            Err(CutError::UnsupportedVersion(_))
+        ));
+    }
+
+    /// The version 0 file with field set to value
+    fn with(field: &str, value: Value) -> Result<Cut, CutError> {
+        let mut cut: Value = serde_json::from_str(VERSION_0).unwrap();
+        let mut target = &mut cut;
+        for name in field.split('.') {
+            target = &mut target[name];
+        }
+        *target = value;
+        from_json(&cut.to_string())
+    }
+
+    #[test]
+    fn validate() {
+        let render = serde_json::to_value(RenderSettings::default()).unwrap();
+        assert!(with("render", render).is_ok());
+        assert!(matches!(
+            with("render.bpm", Value::from(0.0)),
+            Err(CutError::InvalidRender(_))
+        ));
+        assert!(matches!(
+            with("render.sample_rate", Value::from(0)),
+            Err(CutError::InvalidRender(_))
+        ));
+        assert!(matches!(
+            with("fader_curve", serde_json::json!({ "Exponent": -1.0 })),
+            Err(CutError::InvalidFaderCurve(_))
+        ));
+        assert!(matches!(
+            with("fader_lag", Value::from(-0.1)),
+            Err(CutError::InvalidFaderLag(_))
+        ));
+        assert!(matches!(
+            with("bars", Value::from(0.0)),
+            Err(CutError::InvalidBars(_))
+        ));
+        assert!(matches!(
+            with("quantization", Value::from(0)),
+            Err(CutError::InvalidQuantization(_))
+        ));
+
+        let knots = |xs: &[f32]| {
+            let knots = xs
+                .iter()
+                .map(|x| serde_json::json!({ "pos": { "x": x, "y": 0.0 } }));
+            Value::from(knots.collect::<Vec<_>>())
+        };
+        assert!(with("cut_knots", knots(&[-0.25, 0.0, 2.0, 2.25])).is_ok());
+        assert!(matches!(
+            with("cut_knots", knots(&[-0.25, 0.0, 2.0])),
+            Err(CutError::TooFewKnots {
+                lane: "cut",
+                len: 3,
+                min: 4
+            })
+        ));
+        assert!(matches!(
+            with("fader_knots", knots(&[0.0])),
+            Err(CutError::TooFewKnots {
+                lane: "fader",
+                len: 1,
+                min: 2
+            })
+        ));
+        assert!(matches!(
+            with("cut_knots", knots(&[-0.25, 0.0, 0.0, 2.0, 2.25])),
+            Err(CutError::NonMonotonic {
+                lane: "cut",
+                index: 2
+            })
+        ));
+        // fader knots may share x for a hard cut, but not go back
+        assert!(with("fader_knots", knots(&[0.0, 1.0, 1.0, 2.0])).is_ok());
+        assert!(matches!(
+            with("fader_knots", knots(&[0.0, 1.0, 0.5, 2.0])),
+            Err(CutError::NonMonotonic {
+                lane: "fader",
+                index: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn relative_paths() {
+        let path = |dir: &str, path: &str| relative_path(Path::new(dir), Path::new(path));
+        assert_eq!(path("/a/cuts", "/a/cuts/sine.wav"), Some("sine.wav".into()));
+        assert_eq!(path("/a", "/a/b/sine.wav"), Some("b/sine.wav".into()));
+        // sibling directories
+        assert_eq!(
+            path("/a/cuts", "/a/samples/sine.wav"),
+            Some("../samples/sine.wav".into())
+        );
+        assert_eq!(
+            path("/a/b/c", "/d/sine.wav"),
+            Some("../../../d/sine.wav".into())
+        );
+        assert_eq!(
+            path("/a/./cuts/..", "/a/b/../sine.wav"),
+            Some("sine.wav".into())
+        );
+
+        // relative to the working directory
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(path("audio", "audio/sine.wav"), Some("sine.wav".into()));
+        assert_eq!(path("", "sine.wav"), Some("sine.wav".into()));
+        assert_eq!(
+            path("audio", "samples/sine.wav"),
+            Some("../samples/sine.wav".into())
+        );
+        assert_eq!(
+            relative_path(Path::new("audio"), &cwd.join("audio/sine.wav")),
+            Some("sine.wav".into())
+        );
+        assert_eq!(
+            relative_path(&cwd.join("audio"), Path::new("sine.wav")),
+            Some("../sine.wav".into())
+        );
+
+        // different drives share no root
+        #[cfg(windows)]
+        assert_eq!(path(r"C:\a", r"D:\a\sine.wav"), None);
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let path = |dir: &str, path: &str| resolve_path(Path::new(dir), Path::new(path));
+        assert_eq!(path("audio", "sine.wav"), Path::new("audio/sine.wav"));
+        assert_eq!(path("", "sine.wav"), Path::new("sine.wav"));
+        assert_eq!(
+            path("/a/cuts", "../samples/sine.wav"),
+            Path::new("/a/samples/sine.wav")
+        );
+        assert_eq!(path("cuts", "../../sine.wav"), Path::new("../sine.wav"));
+        assert_eq!(path("/a", "../../sine.wav"), Path::new("/sine.wav"));
+        // a path on another root is kept
+        assert_eq!(path("/a/cuts", "/b/sine.wav"), Path::new("/b/sine.wav"));
+    }
+
+    #[test]
+    fn relative_sample_path() {
+        // cut and sample given relative to the working directory
+        let mut cut = from_json(VERSION_0).unwrap();
+        cut.cut_path = "audio/ahh.cut".into();
+        cut.sample_path = Some("audio/sine.wav".into());
+        let value: Value = serde_json::from_str(&to_json(&cut).unwrap()).unwrap();
+        assert_eq!(value["sample_path"], "sine.wav");
+
+        let sample_path = resolve_path(Path::new("audio"), Path::new("sine.wav"));
+        assert_eq!(sample_path, Path::new("audio/sine.wav"));
+    }
+}
@@ -1,424 +1,110 @@
 use crate::{
     config::Config,
+    grid::{self, quantization_name, QUANTIZATIONS},
+    model::{CheckPointData, Cut, FaderCurve, IndexKnot, Interpolation, Knot, Resize},
+    patterns::PatternSettings,
     sinc,
-    wav_panel::{Wav, WavData},
+    wav_data::SAMPLE_EXTENSIONS,
 };
 use egui::epaint::PathShape;
 use egui::*;
 use epaint::RectShape;
-use splines::{Interpolation, Spline};
-
-use serde::{Deserialize, Serialize};
-use std::{fs::File, io::prelude::*, path::PathBuf};
 
 use log::{debug, trace};
+use std::path::{Path, PathBuf};
 /// cut_panel
 ///
-/// A cut is defined by a spline with CatmullRom interpolation.
-/// Left and right knots are outside of the cut region by 1/4 bar.
-/// Second left endpoint defines S the start position of the sample.
-/// Second right endpoint defines E the end position of the sample.
-///
-/// The loop option forces S <-> E, which ensures that the cut can be smoothly looped.
-
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
-pub struct Knot {
-    /// x position in terms of bars. 0.25 -> 1st quarter in 1st bar
-    /// y position in terms of relative sample position 0.0 beginning of sample 1.0 end of sample.
-    pos: Pos2,
-
-    /// Run-time only data
-    #[serde(skip)]
-    selected: bool,
-}
-
-#[derive(Debug)]
-enum CheckPointData {
-    CutKnots(Vec<Knot>),
-    FaderKnots(Vec<Knot>),
-    CutKnot(IndexKnot),
-    FaderKnot(IndexKnot),
-}
-
-#[derive(Debug, Default, Clone)]
-struct IndexKnot {
-    index: usize,
-    knot: Knot,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Cut {
-    /// path to the cut
-    pub cut_path: PathBuf,
-
-    /// path to the sample
-    pub sample_path: Option<PathBuf>,
-
-    /// Quantization 4 -> 1/4 = 0.25 (quarter notes), 16-> 1/16 (six teens), etc.
-    quantization: u32,
-
-    /// Length in terms of bars, e.g. 1.0 amounts to 4 quarter notes, etc.
-    bars: f32,
-
-    /// The control points.
-    cut_knots: Vec<Knot>,
-
-    /// Fader
-    fader_knots: Vec<Knot>,
-
-    /// Looping, the end point equates the start point
-    looping: bool,
-
-    /// Warping, the samples will warp across start/end
-    warping: bool,
+/// A view over a `model::Cut`, holding the selection and drag state of the editor.
 
-    /// Wav
-    pub wav: Wav,
-
-    /// Run-time only data
-
-    /// Undo stack
-    #[serde(skip)]
-    undo: Vec<Vec<CheckPointData>>,
-
-    #[serde(skip)]
-    redo: Vec<Vec<CheckPointData>>,
-
-    /// Cut Spline
-    #[serde(skip)]
-    cut_spline: Spline<f32, f32>,
-
-    /// Fader Spline
-    #[serde(skip)]
-    fader_spline: Spline<f32, f32>,
-
-    /// Wav Data
-    #[serde(skip)]
-    pub wav_data: WavData,
+#[derive(Default)]
+pub struct CutPanel {
+    /// The cut
+    pub cut: Cut,
 
     /// Select rect
-    #[serde(skip)]
     select_start: Pos2,
 
     /// Select end
-    #[serde(skip)]
     select_end: Pos2,
 
     /// Select drag
-    #[serde(skip)]
     select_drag: bool,
 
     /// Move drag
-    #[serde(skip)]
     move_drag: bool,
 
     /// Pos for the start of moving points
-    #[serde(skip)]
     move_start: Pos2,
 
     /// Last position when moving
-    #[serde(skip)]
     move_last: Pos2,
 
     /// Start positions for each cut knot
-    #[serde(skip)]
     move_cut_initial: Vec<Knot>,
 
     /// Start positions for each fader knot
-    #[serde(skip)]
     move_fader_initial: Vec<Knot>,
 
     /// initial position for single knot drag
-    #[serde(skip)]
     move_knot_initial: IndexKnot,
 
     /// Cursor
-    #[serde(skip)]
     cursor: Option<Pos2>,
 
     /// Value
-    #[serde(skip)]
     value: Option<f32>,
-}
 
-impl Default for Cut {
-    fn default() -> Self {
-        let cut_knots = vec![
-            // start top left
-            Knot {
-                pos: pos2(-0.25, 0.0),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(0.0, 0.0),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(1.0, 0.5),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(2.0, 1.0),
-                selected: false,
-            },
-            // end top right
-            Knot {
-                pos: pos2(2.25, 1.0),
-                selected: false,
-            },
-        ];
-        let cut_spline = Spline::from_iter(
-            cut_knots
-                .iter()
-                .map(|k| splines::Key::new(k.pos.x, k.pos.y, Interpolation::CatmullRom)),
-        );
+    /// Length being edited, applied by Set
+    bars_edit: Option<f32>,
 
-        let fader_knots = vec![
-            Knot {
-                pos: pos2(0.0, 0.0),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(1.0, 0.0),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(1.0, 1.0),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(1.5, 1.0),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(1.5, 0.0),
-                selected: false,
-            },
-            Knot {
-                pos: pos2(2.0, 0.0),
-                selected: false,
-            },
-        ];
+    /// How knots follow a length change
+    resize: Resize,
 
-        let fader_spline = Spline::from_iter(
-            fader_knots
-                .iter()
-                .map(|k| splines::Key::new(k.pos.x, k.pos.y, Interpolation::Linear)),
-        );
-
-        let bars = 2.0;
-        let quantization = 16;
-
-        Self {
-            cut_path: PathBuf::new(),
-            sample_path: None,
-            quantization,
-            bars,
-            cut_knots,
-            fader_knots,
-            cut_spline,
-            fader_spline,
-            wav: Wav::default(),
-            wav_data: WavData::default(),
-
-            // Non persistent data
-            undo: vec![],
-            redo: vec![],
-            // needs_save: false,
-            select_start: Pos2::ZERO,
-            select_end: Pos2::ZERO,
-            select_drag: false,
-            move_drag: false,
-            move_start: Pos2::ZERO,
-            move_last: Pos2::ZERO,
-            move_cut_initial: vec![],
-            move_fader_initial: vec![],
-            move_knot_initial: IndexKnot::default(),
-            cursor: None,
-            value: None,
-            looping: false,
-            warping: false,
-        }
-    }
+    /// Scratch pattern to insert
+    pattern: PatternSettings,
 }
 
 #[derive(Default)]
-pub struct OptCut(pub Option<Cut>);
+pub struct OptCut(pub Option<CutPanel>);
 
 impl OptCut {
     /// get name
     pub fn get_name(&self) -> String {
-        if let Some(cut) = &self.0 {
-            cut.get_name()
+        if let Some(panel) = &self.0 {
+            panel.cut.get_name()
         } else {
             "...".to_string()
         }
     }
 }
 
-impl Cut {
-    // Sample spline in bars
-    pub fn sample_spline(&self, bar_pos: f32) -> Option<f32> {
-        self.cut_spline.clamped_sample(bar_pos)
-    }
-
-    pub fn get_bars(&self) -> f32 {
-        self.bars
-    }
-
-    // needs save if undo len > 0
-    pub fn needs_save(&self) -> bool {
-        !self.undo.is_empty() || self.wav.needs_save()
-    }
-
-    // name
-    pub fn name(&self) -> String {
-        self.cut_path
-            .file_name()
-            .map_or("<TBD>".to_string(), |path| {
-                path.to_string_lossy().to_string()
-            })
-    }
-
-    // get name
-    pub fn get_name(&self) -> String {
-        format!(
-            "{}{}",
-            self.name(),
-            if self.needs_save() { "*" } else { "" }
-        )
-    }
-
-    // get undo len
-    pub fn get_undo_len(&self) -> usize {
-        self.undo.len() + self.wav.get_undo_len()
-    }
-
-    // get redo len
-    pub fn get_redo_len(&self) -> usize {
-        self.redo.len() + self.wav.get_redo_len()
+impl CutPanel {
+    pub fn new(cut: Cut) -> Self {
+        CutPanel {
+            cut,
+            ..CutPanel::default()
+        }
     }
 
-    // load file
-    pub fn load_file() -> Result<(Cut, &'static str), String> {
+    // load file, the sample is converted to the project rate
+    pub fn load_file(project_rate: u32) -> Result<(CutPanel, String), String> {
         match rfd::FileDialog::new()
-            .add_filter("wav/cut", &["wav", "cut"])
-            .add_filter("wav", &["wav"])
+            .add_filter("sample/cut", &["wav", "mp3", "flac", "ogg", "cut"])
+            .add_filter("sample", &SAMPLE_EXTENSIONS)
             .add_filter("cut", &["cut"])
             .add_filter(".*", &["*"])
             .set_directory("./audio/")
             .pick_file()
         {
-            Some(mut path) => {
-                trace!("path {:?}", path);
-
-                match path.extension() {
-                    Some(ext) => {
-                        trace!("ext {:?}", ext);
-                        match ext.to_str() {
-                            Some("wav") => {
-                                // loading wav only, set the cut to default
-                                debug!("load wav (only)");
-                                let mut cut = Cut {
-                                    wav_data: WavData::load_wav_data(path.clone())?,
-                                    ..Cut::default()
-                                };
-
-                                cut.wav.set_data_len(cut.wav_data.len);
-                                debug!("path {}", path.display());
-                                cut.sample_path = Some(path.clone());
-                                path.set_extension("cut");
-                                cut.cut_path = path;
-                                Ok((cut, "wav"))
-                            }
-                            Some("cut") => {
-                                debug!("load cut");
-                                if let Ok(mut file) = File::open(path) {
-                                    let mut json = String::new();
-                                    file.read_to_string(&mut json).unwrap();
-                                    trace!("json {}", json);
-                                    let mut cut: Cut = serde_json::from_str(&json).unwrap();
-                                    trace!("cut {:?}", cut);
-
-                                    if let Some(sample_path) = cut.sample_path.clone() {
-                                        cut.wav_data = WavData::load_wav_data(sample_path)?;
-                                    }
-                                    cut.cut_spline_update();
-                                    cut.fader_spline_update();
-
-                                    Ok((cut, "cut"))
-                                } else {
-                                    Err("Could not load file".to_string())
-                                }
-                            }
-
-                            _ => Err("Filetype not supported".to_string()),
-                        }
-                    }
-                    _ => Err("Filetype not supported".to_string()),
-                }
-            }
+            Some(path) => CutPanel::open(path, project_rate),
             None => Err("Load cancelled".to_string()),
         }
     }
 
-    /// call to update cut spline when knots are changed
-    pub fn cut_spline_update(&mut self) {
-        // add a knot to the spline
-        #[inline(always)]
-        fn key(knot: &Knot) -> splines::Key<f32, f32> {
-            splines::Key::new(knot.pos.x, knot.pos.y, Interpolation::CatmullRom)
-        }
-        trace!("update knots and spline");
-        let len = self.cut_knots.len();
-        // ensure that endpoints are aligned
-        self.cut_knots[0].pos.y = self.cut_knots[1].pos.y;
-        self.cut_knots[len - 1].pos.y = self.cut_knots[len - 2].pos.y;
-
-        // add knots besides last two
-        self.cut_spline = Spline::from_iter(self.cut_knots[..len - 2].iter().map(key));
-
-        // add last two knots
-        if self.looping {
-            self.cut_spline.add(splines::Key::new(
-                self.cut_knots[len - 2].pos.x,
-                self.cut_knots[0].pos.y,
-                Interpolation::CatmullRom,
-            ));
-            self.cut_spline.add(splines::Key::new(
-                self.cut_knots[len - 1].pos.x,
-                self.cut_knots[0].pos.y,
-                Interpolation::CatmullRom,
-            ));
-        } else {
-            self.cut_spline.add(key(&self.cut_knots[len - 2]));
-            self.cut_spline.add(key(&self.cut_knots[len - 1]));
-        }
-    }
-
-    /// call to update spline when knots are changed
-    pub fn fader_spline_update(&mut self) {
-        let len = self.fader_knots.len();
-
-        self.fader_spline = Spline::from_iter(
-            self.fader_knots[..len - 1]
-                .iter()
-                .map(|k| splines::Key::new(k.pos.x, k.pos.y, Interpolation::Linear)),
-        );
-
-        // add last knot
-        if self.looping {
-            self.fader_spline.add(splines::Key::new(
-                self.fader_knots[len - 1].pos.x,
-                0.0,
-                Interpolation::Linear,
-            ));
-        } else {
-            let pos = self.fader_knots[len - 1].pos;
-            self.fader_spline
-                .add(splines::Key::new(pos.x, pos.y, Interpolation::Linear));
-        }
-        debug!("spline update {:?}", self.fader_spline);
+    // open cut or sample at path, the sample is converted to the project rate
+    pub fn open(path: PathBuf, project_rate: u32) -> Result<(CutPanel, String), String> {
+        let (cut, msg) = Cut::load_path(path, Some(project_rate)).map_err(|err| err.to_string())?;
+        Ok((CutPanel::new(cut), msg))
     }
 
     /// get the cursor position
@@ -433,34 +119,18 @@ impl Cut {
 
     /// save cut
     fn save_cut(&mut self) -> String {
-        // Serialize it to a JSON string.
-        let json = serde_json::to_string(&self).unwrap();
-        debug!("save_cut {}", json);
-
-        match File::create(&self.cut_path) {
-            Ok(mut file) => {
-                self.redo = vec![];
-                self.undo = vec![];
-                self.wav.clear_undo_redo();
-                if let Err(err) = file.write_all(json.as_bytes()) {
-                    debug!("Err {:?}", err);
-                    format!("{:?}", err)
-                } else {
-                    format!("Cut saved to {:?}", self.cut_path)
-                }
-            }
-            Err(err) => {
-                format!("{:?}", err)
-            }
+        match self.cut.save() {
+            Ok(()) => format!("Cut saved to {:?}", self.cut.cut_path),
+            Err(err) => err,
         }
     }
 
     /// Save Cut Dialogue
     /// TODO: Does sometimes not pop-up under existing windows
     fn save_cut_dialogue(&mut self) -> String {
-        let directory = self.cut_path.parent().unwrap();
+        let directory = self.cut.cut_path.parent().unwrap();
         debug!("directory : {:?}", directory);
-        let file_name = self.cut_path.file_name().unwrap().to_string_lossy();
+        let file_name = self.cut.cut_path.file_name().unwrap().to_string_lossy();
         if let Some(path) = rfd::FileDialog::new()
             .add_filter("cut", &["cut"])
             .set_directory(directory)
@@ -468,22 +138,147 @@ impl Cut {
             .save_file()
         {
             debug!("cut path {:?}", path);
-            self.cut_path = path;
+            self.cut.cut_path = path;
             self.save_cut()
         } else {
             "Save cancelled.".to_string()
         }
     }
 
+    /// Relink a missing sample, from the candidates found or a file dialog
+    fn ui_content_relink(&mut self, ui: &mut Ui, status: &mut String) {
+        let Some(sample_path) = self.cut.sample_path.clone() else {
+            return;
+        };
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            format!("Sample missing: {}", sample_path.display()),
+        );
+
+        let mut relink = None;
+        for candidate in &self.cut.relink_candidates {
+            if ui.button(candidate.display().to_string()).clicked() {
+                relink = Some(candidate.clone());
+            }
+        }
+        if ui.button("Relink...").clicked() {
+            let directory = self.cut.cut_path.parent().unwrap_or(Path::new("./audio/"));
+            relink = rfd::FileDialog::new()
+                .add_filter("sample", &SAMPLE_EXTENSIONS)
+                .set_directory(directory)
+                .pick_file();
+        }
+
+        if let Some(path) = relink {
+            *status = match self.cut.relink_sample(path.clone()) {
+                Ok(()) => format!("Sample relinked to {:?}", path),
+                Err(err) => err.to_string(),
+            };
+        }
+        ui.separator();
+    }
+
     /// Cut Settings
     pub fn ui_content_settings(&mut self, ui: &mut Ui, status: &mut String) {
-        if ui.checkbox(&mut self.looping, "looping").clicked()
-            || ui.checkbox(&mut self.warping, "warping").clicked()
-        {
-            self.cut_spline_update();
-            self.fader_spline_update();
+        if self.cut.sample_missing() {
+            self.ui_content_relink(ui, status);
         }
 
+        let mut looping = self.cut.is_looping();
+        if ui.checkbox(&mut looping, "looping").clicked() {
+            self.cut.set_looping(looping);
+        }
+        let mut warping = self.cut.is_warping();
+        if ui.checkbox(&mut warping, "warping").clicked() {
+            self.cut.set_warping(warping);
+        }
+        let mut monotone = self.cut.is_monotone();
+        if ui
+            .checkbox(&mut monotone, "monotone")
+            .on_hover_text("Monotone cubic instead of Catmull-Rom, does not overshoot the knots")
+            .clicked()
+        {
+            self.cut.set_monotone(monotone);
+        }
+
+        // fader gain curve, the exponent is kept when reselected
+        let (mut curve, mut lag) = self.cut.get_fader_curve();
+        ui.horizontal(|ui| {
+            ui.label("Fader");
+            ComboBox::from_id_source("cut_fader_curve")
+                .selected_text(curve.name())
+                .show_ui(ui, |ui| {
+                    for c in FaderCurve::ALL {
+                        if ui
+                            .selectable_label(curve.name() == c.name(), c.name())
+                            .clicked()
+                            && curve.name() != c.name()
+                        {
+                            curve = c;
+                        }
+                    }
+                });
+            if let FaderCurve::Exponent(exponent) = &mut curve {
+                ui.add(DragValue::new(exponent).range(0.1..=8.0).speed(0.05));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Cut-in lag");
+            ui.add(Slider::new(&mut lag, 0.0..=0.5));
+        });
+        self.cut.set_fader_curve(curve, lag);
+
+        // length, applied on Set so dragging does not trim repeatedly
+        let mut bars = self.bars_edit.unwrap_or(self.cut.get_bars());
+        ui.horizontal(|ui| {
+            ui.label("Bars");
+            ui.add(
+                DragValue::new(&mut bars)
+                    .range(0.25..=64.0)
+                    .speed(0.05)
+                    .fixed_decimals(2),
+            );
+            ComboBox::from_id_source("cut_resize")
+                .selected_text(self.resize.name())
+                .show_ui(ui, |ui| {
+                    for resize in Resize::ALL {
+                        ui.selectable_value(&mut self.resize, resize, resize.name());
+                    }
+                });
+            if ui
+                .add_enabled(bars != self.cut.get_bars(), Button::new("Set"))
+                .clicked()
+            {
+                let checkpoint = self.cut.set_bars(bars, self.resize);
+                self.cut.push_checkpoint(checkpoint);
+                *status = format!("Length {} bars", bars);
+            }
+        });
+        self.bars_edit = (bars != self.cut.get_bars()).then_some(bars);
+
+        let mut quantization = self.cut.get_quantization();
+        ui.horizontal(|ui| {
+            ui.label("Quantization");
+            ComboBox::from_id_source("cut_quantization")
+                .selected_text(quantization_name(quantization))
+                .show_ui(ui, |ui| {
+                    for q in QUANTIZATIONS {
+                        ui.selectable_value(&mut quantization, q, quantization_name(q));
+                    }
+                });
+        });
+        self.cut.set_quantization(quantization);
+
+        let mut swing = self.cut.get_swing();
+        ui.horizontal(|ui| {
+            ui.label("Swing");
+            ui.add_enabled(
+                !self.cut.grid().is_triplet(),
+                Slider::new(&mut swing, grid::SWING_STRAIGHT..=grid::SWING_MAX).suffix(" %"),
+            );
+        });
+        self.cut.set_swing(swing);
+
         if ui.button("Save Cut").clicked()
             || ui.input_mut(|i| i.consume_key(Modifiers::CTRL, Key::S))
         {
@@ -494,92 +289,81 @@ impl Cut {
             *status = self.save_cut_dialogue();
         }
 
+        ui.separator();
+        ui.label("Pattern");
+        ui.add_space(10.0);
+        self.pattern.ui_content(ui);
+        if ui.button("Insert").clicked() {
+            *status = match self.pattern.generate(self.cut.get_bars()) {
+                Ok((cut_knots, fader_knots)) => {
+                    let checkpoint = self.cut.insert_knots(cut_knots, fader_knots);
+                    self.cut.push_checkpoint(checkpoint);
+                    format!("Inserted {} pattern", self.pattern.pattern.name())
+                }
+                Err(err) => err,
+            };
+        }
+
+        ui.separator();
+        ui.label("Render");
+        ui.add_space(10.0);
+        if self.cut.render.ui_content(ui, &self.cut.cut_path) {
+            self.cut.mark_changed();
+        }
+
         if ui.button("Sample").clicked() {
-            sinc::sinc_resample(self);
+            *status = match sinc::sinc_resample(&self.cut, &self.cut.render) {
+                Ok(path) => format!("Cut rendered to {:?}", path),
+                Err(err) => err,
+            };
         }
     }
 
-    /// main panel
-    pub fn ui_content(&mut self, ui: &mut Ui, config: &Config, height: f32) -> egui::Response {
+    /// main panel, playhead in bars
+    pub fn ui_content(
+        &mut self,
+        ui: &mut Ui,
+        config: &Config,
+        height: f32,
+        playhead: Option<f32>,
+    ) -> egui::Response {
         let (response, painter) = ui.allocate_painter(
             // Vec2::new(ui.available_width(), ui.available_height()),
             Vec2::new(ui.available_width(), height),
             Sense::click_and_drag(),
         );
 
-        let to_screen = emath::RectTransform::from_to(
-            Rect::from_min_size(Pos2::ZERO, response.rect.size()),
-            response.rect,
-        );
-
         // checkpoint
         let mut checkpoint = vec![];
 
         // panel_pos relation to bars
         let width = response.rect.width();
-        let segments = self.bars * self.quantization as f32;
-        let scale = width / segments;
+        let grid = self.cut.grid();
 
         let bars_rect = Rect::from_min_max(
             Pos2::ZERO,
             Pos2 {
-                x: self.bars,
+                x: self.cut.get_bars(),
                 y: 1.0,
             },
         );
 
-        let bars_to_screen = emath::RectTransform::from_to(bars_rect, response.rect);
+        // the fader lane at the bottom, config.fader_height high
+        let fader_height = config.fader_height.clamp(0.0, response.rect.height() / 2.0);
+        let (cut_rect, fader_rect) = response
+            .rect
+            .split_top_bottom_at_y(response.rect.max.y - fader_height);
+        let bars_to_screen = emath::RectTransform::from_to(bars_rect, cut_rect);
+        let fader_to_screen = emath::RectTransform::from_to(bars_rect, fader_rect);
 
         let mut primary_clicked = response.clicked_by(PointerButton::Primary);
         let mut _middle_clicked = response.clicked_by(PointerButton::Middle);
         let secondary_clicked = response.clicked_by(PointerButton::Secondary);
 
-        let mut cut_update = false;
-        let mut fader_update = false;
-
         // delete knots
         if ui.input(|i| i.key_pressed(egui::Key::Delete)) {
             trace!("delete");
-
-            // cut knots
-            let mut index = 0;
-            let len = self.cut_knots.len();
-            let mut cut_knots = self.cut_knots.clone();
-            let mut delete_any = false;
-
-            cut_knots.retain(|k| {
-                index += 1;
-                let delete = !(k.selected && index > 2 && index < len - 1);
-                cut_update |= delete;
-                delete_any |= delete;
-                delete
-            });
-
-            if delete_any {
-                debug!("delete cut knots");
-                checkpoint.push(CheckPointData::CutKnots(self.cut_knots.clone()));
-                self.cut_knots = cut_knots;
-            }
-
-            // fader knots
-            let mut index = 0;
-            let len = self.fader_knots.len();
-            let mut fader_knots = self.fader_knots.clone();
-            let mut delete_any = false;
-
-            fader_knots.retain(|k| {
-                index += 1;
-                let delete = !(k.selected && index > 1 && index < len);
-                fader_update |= delete;
-                delete_any |= delete;
-                delete
-            });
-
-            if delete_any {
-                debug!("delete fader knots");
-                checkpoint.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
-                self.fader_knots = fader_knots;
-            }
+            checkpoint.extend(self.cut.delete_selected());
         }
 
         // selection
@@ -588,19 +372,7 @@ impl Cut {
             || response.double_clicked_by(PointerButton::Secondary)
         {
             debug!("escape or double right click");
-            if self.cut_knots.iter().any(|cut_knot| cut_knot.selected) {
-                checkpoint.push(CheckPointData::CutKnots(self.cut_knots.clone()));
-            }
-            if self
-                .fader_knots
-                .iter()
-                .any(|fader_knot| fader_knot.selected)
-            {
-                checkpoint.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
-            }
-
-            self.cut_knots.iter_mut().for_each(|k| k.selected = false);
-            self.fader_knots.iter_mut().for_each(|k| k.selected = false);
+            checkpoint.extend(self.cut.clear_selection());
         }
 
         if response.drag_started_by(PointerButton::Secondary) {
@@ -616,31 +388,10 @@ impl Cut {
             debug!("select end {:?} ", pos);
             let rect = Rect::from_two_pos(self.select_start, self.select_end);
 
-            // cut knots
-            let mut cut_knots_contained = false;
-            let cut_knots = self.cut_knots.clone();
-            self.cut_knots.iter_mut().for_each(|cut_knot| {
-                if rect.contains(bars_to_screen * cut_knot.pos) {
-                    if !cut_knots_contained {
-                        checkpoint.push(CheckPointData::CutKnots(cut_knots.clone()));
-                        cut_knots_contained = true;
-                    }
-                    cut_knot.selected ^= true;
-                }
-            });
-
-            // fader knots
-            let mut fader_knots_contained = false;
-            let fader_knots = self.fader_knots.clone();
-            self.fader_knots.iter_mut().for_each(|fader_knot| {
-                if rect.contains(bars_to_screen * fader_knot.pos) {
-                    if !fader_knots_contained {
-                        checkpoint.push(CheckPointData::FaderKnots(fader_knots.clone()));
-                        fader_knots_contained = true;
-                    }
-                    fader_knot.selected ^= true;
-                }
-            });
+            checkpoint.extend(self.cut.toggle_select(
+                |pos| rect.contains(bars_to_screen * pos),
+                |pos| rect.contains(fader_to_screen * pos),
+            ));
 
             self.select_drag = false;
         }
@@ -661,32 +412,29 @@ impl Cut {
             )));
         }
 
-        let cut_knots = self.cut_knots.clone();
-        let fader_knots = self.fader_knots.clone();
         // drag all knots
         if response.drag_started_by(PointerButton::Primary) {
             self.move_drag = true;
             self.move_start = response.interact_pointer_pos().unwrap();
             self.move_last = self.move_start;
-            self.move_cut_initial = self.cut_knots.clone();
-            self.move_fader_initial = self.fader_knots.clone();
+            self.move_cut_initial = self.cut.cut_knots().to_vec();
+            self.move_fader_initial = self.cut.fader_knots().to_vec();
             trace!("start move {:?}", self.move_start);
         }
 
         if response.drag_stopped_by(PointerButton::Primary) {
             self.move_drag = true;
             trace!("end move");
-            if self.cut_knots != self.move_cut_initial {
+            if self.cut.cut_knots() != self.move_cut_initial {
                 checkpoint.push(CheckPointData::CutKnots(self.move_cut_initial.clone()));
             }
-            if self.fader_knots != self.move_fader_initial {
+            if self.cut.fader_knots() != self.move_fader_initial {
                 checkpoint.push(CheckPointData::FaderKnots(self.move_fader_initial.clone()));
             }
             self.move_drag = false;
         }
 
         if response.dragged_by(PointerButton::Primary) {
-            cut_update = true;
             let scr_pos = response.interact_pointer_pos().unwrap();
             let delta = scr_pos - self.move_last;
             self.move_last = scr_pos;
@@ -696,340 +444,214 @@ impl Cut {
 
             trace!("rel {:?}, k rel {:?}", rel, bar_rel);
 
-            if delta.x > 0.0 {
-                trace!("right");
-
-                // cut knots
-                // right. we have to update rightmost knot first
-                // exclude first 2 and last 2 knots, they have fixed x positions
-                for i in (2..cut_knots.len() - 2).rev() {
-                    if self.cut_knots[i].selected {
-                        let knot_pos_x = ((self.move_cut_initial[i].pos.x + bar_rel.x)
-                            * (self.quantization as f32))
-                            .round()
-                            / (self.quantization as f32);
-
-                        if knot_pos_x < self.cut_knots[i + 1].pos.x
-                            && knot_pos_x > self.cut_knots[i - 1].pos.x
-                        {
-                            self.cut_knots[i].pos.x = knot_pos_x;
-                        }
-                    }
-                }
+            self.cut.move_selected(
+                &self.move_cut_initial,
+                &self.move_fader_initial,
+                bar_rel,
+                delta.x,
+            );
+        }
 
-                // fader knots
-                // right. we have to update rightmost knot first
-                // exclude first and last knots, they have fixed x positions
-                for i in (1..fader_knots.len() - 1).rev() {
-                    if self.fader_knots[i].selected {
-                        let knot_pos_x = ((self.move_fader_initial[i].pos.x + bar_rel.x)
-                            * (self.quantization as f32))
-                            .round()
-                            / (self.quantization as f32);
-
-                        if knot_pos_x <= self.fader_knots[i + 1].pos.x
-                            && knot_pos_x >= self.fader_knots[i - 1].pos.x
-                        {
-                            self.fader_knots[i].pos.x = knot_pos_x;
-                        }
+        // cut knots
+        let control_point_radius = 8.0;
+        let cut_len = self.cut.cut_knots().len();
+        let mut cut_knot_shapes: Vec<Shape> = vec![];
+        for index in 1..if self.cut.is_looping() {
+            cut_len - 2
+        } else {
+            cut_len - 1
+        } {
+            let size = Vec2::splat(2.0 * control_point_radius);
+            let point_in_screen = bars_to_screen * self.cut.cut_knots()[index].pos;
+
+            let point_rect = Rect::from_center_size(point_in_screen, size);
+
+            let point_id = response.id.with(index - 1);
+            let point_click = ui.interact(point_rect, point_id, Sense::click());
+
+            // toggle select on click
+            if point_click.clicked() {
+                self.cut.toggle_cut_knot(index);
+                primary_clicked = false;
+            }
+
+            // interpolation of this and the selected knots
+            point_click.context_menu(|ui| {
+                let current = self.cut.cut_knots()[index].interpolation;
+                for interpolation in Interpolation::ALL {
+                    if ui
+                        .radio(current == interpolation, interpolation.name())
+                        .clicked()
+                    {
+                        checkpoint.extend(self.cut.set_interpolation(index, interpolation));
+                        ui.close_menu();
                     }
                 }
-            } else if delta.x < 0.0 {
-                trace!("left");
-
-                // cut knots
-                // left we update leftmost knot first
-                // we exclude first 2 and last 2 knots, they have fixed positions
-                for i in 2..cut_knots.len() - 2 {
-                    if self.cut_knots[i].selected {
-                        let knot_pos_x = ((self.move_cut_initial[i].pos.x + bar_rel.x)
-                            * (self.quantization as f32))
-                            .round()
-                            / (self.quantization as f32);
-
-                        if knot_pos_x > self.cut_knots[i - 1].pos.x
-                            && knot_pos_x < self.cut_knots[i + 1].pos.x
-                        {
-                            self.cut_knots[i].pos.x = knot_pos_x;
-                        }
-                    }
+                ui.separator();
+                let mut handles = self.cut.cut_knots()[index].handles.is_some();
+                if ui.checkbox(&mut handles, "tangent handles").clicked() {
+                    checkpoint.extend(self.cut.set_handles(index, handles));
+                    ui.close_menu();
                 }
+            });
 
-                // fader knots
-                // left we update leftmost knot first
-                // we exclude first and last knots, they have fixed positions
-                for i in 1..fader_knots.len() - 1 {
-                    if self.fader_knots[i].selected {
-                        let knot_pos_x = ((self.move_fader_initial[i].pos.x + bar_rel.x)
-                            * (self.quantization as f32))
-                            .round()
-                            / (self.quantization as f32);
-
-                        if knot_pos_x >= self.fader_knots[i - 1].pos.x
-                            && knot_pos_x <= self.fader_knots[i + 1].pos.x
-                        {
-                            self.fader_knots[i].pos.x = knot_pos_x;
-                        }
-                    }
-                }
-            }
+            let point_response = ui.interact(point_rect, point_id, Sense::drag());
 
-            // cut knots up/down
-            for i in 1..cut_knots.len() - 1 {
-                if self.cut_knots[i].selected {
-                    self.cut_knots[i].pos.y =
-                        (self.move_cut_initial[i].pos.y + bar_rel.y).clamp(0.0, 1.0);
-                }
+            if point_response.drag_started() {
+                debug!("started - undo cut_knots");
+                self.move_knot_initial = IndexKnot {
+                    index,
+                    knot: self.cut.cut_knots()[index],
+                };
             }
-        }
 
-        // cut knots
-        let control_point_radius = 8.0;
-        let cut_knot_shapes: Vec<Shape> = self.cut_knots[1..if self.looping {
-            cut_knots.len() - 2
-        } else {
-            cut_knots.len() - 1
-        }]
-            .iter_mut()
-            .enumerate()
-            .map(|(i, k)| {
-                let size = Vec2::splat(2.0 * control_point_radius);
-                let point_in_screen = bars_to_screen * k.pos;
-
-                let point_rect = Rect::from_center_size(point_in_screen, size);
-
-                let point_id = response.id.with(i);
-                let point_click = ui.interact(point_rect, point_id, Sense::click());
-
-                // toggle select on click
-                if point_click.clicked() {
-                    k.selected ^= true;
-                    primary_clicked = false;
-                }
+            if point_response.drag_stopped() {
+                debug!("released - undo cut_knots");
+                checkpoint.push(CheckPointData::CutKnot(self.move_knot_initial.clone()));
+            }
 
-                let point_response = ui.interact(point_rect, point_id, Sense::drag());
+            if point_response.dragged() {
+                let pos = point_response.interact_pointer_pos().unwrap();
+                let knot_pos = bars_to_screen.inverse().transform_pos(pos);
+                trace!("single_knot_drag {:?}", knot_pos.x);
+                self.cut.move_cut_knot(index, knot_pos);
+            }
 
-                if point_response.drag_started() {
-                    debug!("started - undo cut_knots");
-                    self.move_knot_initial = IndexKnot {
-                        index: i + 1,
-                        knot: *k,
-                    };
-                }
+            let k = self.cut.cut_knots()[index];
+            cut_knot_shapes.push(Shape::circle_stroke(
+                bars_to_screen * k.pos,
+                control_point_radius,
+                if k.selected {
+                    config.stroke_knot_selected
+                } else {
+                    config.stroke_knot
+                },
+            ));
 
-                if point_response.drag_stopped() {
-                    debug!("released - undo cut_knots");
-                    checkpoint.push(CheckPointData::CutKnot(self.move_knot_initial.clone()));
-                }
+            // tangent handles, dragged up and down
+            if let Some((left, right)) = self.cut.handle_positions(index) {
+                let handle_size = Vec2::splat(control_point_radius);
+                for (is_right, pos) in [(false, left), (true, right)] {
+                    let handle_in_screen = bars_to_screen * pos;
+                    let handle_rect = Rect::from_center_size(handle_in_screen, handle_size);
+                    let handle_id = response.id.with(("handle", index, is_right));
+                    let handle_response =
+                        ui.interact(handle_rect, handle_id, Sense::click_and_drag());
+
+                    if handle_response.drag_started() {
+                        self.move_knot_initial = IndexKnot {
+                            index,
+                            knot: self.cut.cut_knots()[index],
+                        };
+                    }
 
-                if point_response.dragged() {
-                    let pos = point_response.interact_pointer_pos().unwrap();
-                    let mut knot_pos = bars_to_screen.inverse().transform_pos(pos);
-                    trace!("single_knot_drag {:?}", knot_pos.x);
-                    knot_pos.x = (knot_pos.x * (self.quantization as f32)).round()
-                        / (self.quantization as f32);
-                    trace!("rounded {:?}", knot_pos.x);
-
-                    // never move first 2 and last 2 knots in x direction
-                    if i > 0 && i < cut_knots.len() - 3 {
-                        if knot_pos.x > k.pos.x {
-                            // right
-                            if knot_pos.x < cut_knots[i + 2].pos.x {
-                                k.pos.x = knot_pos.x;
-                            }
-                        } else if knot_pos.x < k.pos.x {
-                            // left
-                            if knot_pos.x > cut_knots[i].pos.x {
-                                k.pos.x = knot_pos.x;
-                            }
-                        }
+                    if handle_response.drag_stopped() {
+                        checkpoint.push(CheckPointData::CutKnot(self.move_knot_initial.clone()));
                     }
 
-                    k.pos.y = knot_pos.y.clamp(0.0, 1.0); // clamp to range
+                    if handle_response.dragged() {
+                        let pos = handle_response.interact_pointer_pos().unwrap();
+                        let y = bars_to_screen.inverse().transform_pos(pos).y;
+                        self.cut.move_handle(index, is_right, y);
+                    }
 
-                    cut_update = true;
+                    cut_knot_shapes.push(Shape::line_segment(
+                        [bars_to_screen * k.pos, handle_in_screen],
+                        config.stroke_line,
+                    ));
+                    cut_knot_shapes.push(Shape::Rect(RectShape::stroke(
+                        handle_rect,
+                        Rounding::ZERO,
+                        config.stroke_knot,
+                    )));
                 }
-
-                Shape::circle_stroke(
-                    point_in_screen,
-                    control_point_radius,
-                    if k.selected {
-                        config.stroke_knot_selected
-                    } else {
-                        config.stroke_knot
-                    },
-                )
-            })
-            .collect();
+            }
+        }
 
         // fader knots
         let control_point_radius = 8.0;
-
-        let fader_knot_shapes: Vec<Shape> = self.fader_knots[0..if self.looping {
-            fader_knots.len() - 1
+        let fader_len = self.cut.fader_knots().len();
+        let mut fader_knot_shapes: Vec<Shape> = vec![];
+        for index in 0..if self.cut.is_looping() {
+            fader_len - 1
         } else {
-            fader_knots.len()
-        }]
-            .iter_mut()
-            .enumerate()
-            .map(|(i, k)| {
-                let size = Vec2::splat(2.0 * control_point_radius);
-                let point_in_screen = bars_to_screen * k.pos;
-
-                let point_rect = Rect::from_center_size(point_in_screen, size);
-
-                let point_id = response.id.with(i + cut_knots.len());
-                let point_click = ui.interact(point_rect, point_id, Sense::click());
-
-                // toggle select on click
-                if point_click.clicked() {
-                    k.selected ^= true;
-                    primary_clicked = false;
-                }
+            fader_len
+        } {
+            let size = Vec2::splat(2.0 * control_point_radius);
+            let point_in_screen = fader_to_screen * self.cut.fader_knots()[index].pos;
 
-                let point_response = ui.interact(point_rect, point_id, Sense::drag());
+            let point_rect = Rect::from_center_size(point_in_screen, size);
 
-                if point_response.drag_started() {
-                    debug!("started - undo fader_knots");
-                    self.move_knot_initial = IndexKnot { index: i, knot: *k };
-                }
+            let point_id = response.id.with(index + cut_len);
+            let point_click = ui.interact(point_rect, point_id, Sense::click());
 
-                if point_response.drag_stopped() {
-                    debug!("released - undo fader_knots");
-                    checkpoint.push(CheckPointData::FaderKnot(self.move_knot_initial.clone()));
-                }
+            // toggle select on click
+            if point_click.clicked() {
+                self.cut.toggle_fader_knot(index);
+                primary_clicked = false;
+            }
 
-                if point_response.dragged() {
-                    let pos = point_response.interact_pointer_pos().unwrap();
-                    let mut knot_pos = bars_to_screen.inverse().transform_pos(pos);
-                    trace!("single_knot_drag {:?}", knot_pos.x);
-                    knot_pos.x = (knot_pos.x * (self.quantization as f32)).round()
-                        / (self.quantization as f32);
-                    trace!("rounded {:?}", knot_pos.x);
-
-                    // never move last knot
-                    // we could think about clamping to 0.0, 1.0 for binary fader
-                    if i > 0 && i < fader_knots.len() - 1 {
-                        if knot_pos.x >= k.pos.x {
-                            // right
-                            trace!("-- right");
-                            if knot_pos.x <= fader_knots[i + 1].pos.x {
-                                k.pos.x = knot_pos.x;
-                            }
-                        } else if knot_pos.x < k.pos.x {
-                            // left
-                            trace!("-- left");
-                            if knot_pos.x >= fader_knots[i - 1].pos.x {
-                                k.pos.x = knot_pos.x;
-                            }
-                        }
-                    }
+            let point_response = ui.interact(point_rect, point_id, Sense::drag());
 
-                    k.pos.y = knot_pos.y.clamp(0.0, 1.0); // clamp to range
+            if point_response.drag_started() {
+                debug!("started - undo fader_knots");
+                self.move_knot_initial = IndexKnot {
+                    index,
+                    knot: self.cut.fader_knots()[index],
+                };
+            }
 
-                    fader_update = true;
-                }
+            if point_response.drag_stopped() {
+                debug!("released - undo fader_knots");
+                checkpoint.push(CheckPointData::FaderKnot(self.move_knot_initial.clone()));
+            }
 
-                Shape::Rect(RectShape::stroke(
-                    point_rect,
-                    0.0,
-                    if k.selected {
-                        config.stroke_knot_selected
-                    } else {
-                        config.stroke_knot
-                    },
-                ))
-            })
-            .collect();
+            if point_response.dragged() {
+                let pos = point_response.interact_pointer_pos().unwrap();
+                let knot_pos = fader_to_screen.inverse().transform_pos(pos);
+                trace!("single_knot_drag {:?}", knot_pos.x);
+                self.cut.move_fader_knot(index, knot_pos);
+            }
+
+            let k = self.cut.fader_knots()[index];
+            fader_knot_shapes.push(Shape::Rect(RectShape::stroke(
+                Rect::from_center_size(fader_to_screen * k.pos, size),
+                0.0,
+                if k.selected {
+                    config.stroke_knot_selected
+                } else {
+                    config.stroke_knot
+                },
+            )));
+        }
 
         // add new cut knot point
         if primary_clicked {
             // screen position
             let click_pos = response.interact_pointer_pos().unwrap();
             // data point
-            let mut pos = bars_to_screen.inverse().transform_pos_clamped(click_pos);
-
-            let round_x = (pos.x * self.quantization as f32).round() / (self.quantization as f32);
-
-            pos.x = round_x;
-
-            // insert, or move
-            let cp = self.cut_knots.clone().into_iter();
-
-            checkpoint.push(CheckPointData::CutKnots(self.cut_knots.clone()));
-
-            if self
-                .cut_knots
-                .iter_mut()
-                .find_map(|k| {
-                    if k.pos.x == pos.x {
-                        k.pos.y = pos.y;
-                        Some(())
-                    } else {
-                        None
-                    }
-                })
-                .is_none()
-            {
-                trace!("new cut knot point");
-                let (head, mut tail): (Vec<_>, Vec<_>) = cp.partition(|k| pos.x < k.pos.x);
-
-                tail.push(Knot {
-                    pos,
-                    selected: false,
-                });
-                tail.extend(head);
-
-                self.cut_knots = tail;
-
-                trace!("cut knots {:?}", self.cut_knots);
+            if cut_rect.contains(click_pos) {
+                let pos = bars_to_screen.inverse().transform_pos_clamped(click_pos);
+                checkpoint.push(self.cut.insert_cut_knot(pos));
             }
-            cut_update = true;
         }
 
         // add fader knot
         if secondary_clicked {
             // screen position
             let click_pos = response.interact_pointer_pos().unwrap();
-            // position of the click in bars
-            let mut pos = bars_to_screen.inverse().transform_pos_clamped(click_pos);
-            // quantized in bars
-            let round_x = (pos.x * self.quantization as f32).round() / (self.quantization as f32);
-
-            pos.x = round_x;
-
-            // insert, or move
-            let fader_knots = self.fader_knots.clone().into_iter();
-
-            checkpoint.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
-
-            debug!("new fader knot point");
-            let (head, mut tail): (Vec<_>, Vec<_>) = fader_knots.partition(|k| pos.x < k.pos.x);
-
-            tail.push(Knot {
-                pos,
-                selected: false,
-            });
-            tail.extend(head);
-
-            self.fader_knots = tail;
-
-            debug!("fader knots {:?}", self.fader_knots);
-
-            fader_update = true;
-        }
-
-        if cut_update {
-            self.cut_spline_update();
+            // position of the click in bars, in the fader lane
+            if fader_rect.contains(click_pos) {
+                let pos = fader_to_screen.inverse().transform_pos_clamped(click_pos);
+                checkpoint.push(self.cut.insert_fader_knot(pos));
+            }
         }
 
-        if fader_update {
-            self.fader_spline_update();
-        }
+        let cut = &self.cut;
 
         // draw spline
-        let start = self.cut_knots[1].pos.x; // to ensure we have two knots on either side
-        let end = self.cut_knots[self.cut_knots.len() - 2].pos.x;
+        let start = cut.cut_knots()[1].pos.x; // to ensure we have two knots on either side
+        let end = cut.cut_knots()[cut.cut_knots().len() - 2].pos.x;
 
         let interval = end - start;
         let points = width / config.step_size as f32;
@@ -1046,20 +668,10 @@ impl Cut {
         for i in 0..=points as usize {
             let t = i as f32 * step + start;
 
-            if let Some(y) = self.cut_spline.sample(t) {
-                let y = if self.warping {
-                    if y > 1.0 {
-                        y - 1.0
-                    } else if y < 0.0 {
-                        y + 1.0
-                    } else {
-                        y
-                    }
-                } else {
-                    y.clamp(0.0, 1.0)
-                };
+            if let Some(y) = cut.spline_value(t) {
+                let y = cut.wrap_value(y);
 
-                let fader_y = self.fader_spline.sample(t).unwrap();
+                let fader_y = cut.sample_gain(t).unwrap_or(0.0);
                 if fader_y == 0.0 {
                     cut_solid.push(bars_to_screen * Pos2 { x: t, y });
                     if fader_state {
@@ -1086,7 +698,7 @@ impl Cut {
                 bars_to_screen
                     * Pos2 {
                         x: end,
-                        y: self.cut_spline.sample(end - 0.000001).unwrap(),
+                        y: cut.spline_value(end - 0.000001).unwrap_or(0.0),
                     },
             );
         } else {
@@ -1094,7 +706,7 @@ impl Cut {
                 bars_to_screen
                     * Pos2 {
                         x: end,
-                        y: self.cut_spline.sample(end - 0.000001).unwrap(),
+                        y: cut.spline_value(end - 0.000001).unwrap_or(0.0),
                     },
             );
         }
@@ -1111,8 +723,8 @@ impl Cut {
 
         // draw connecting lines for cut spline
         if config.knot_line {
-            let points_in_screen: Vec<Pos2> = self
-                .cut_knots
+            let points_in_screen: Vec<Pos2> = cut
+                .cut_knots()
                 .iter()
                 .map(|k| bars_to_screen * k.pos)
                 .collect();
@@ -1120,22 +732,43 @@ impl Cut {
         }
 
         // draw connecting lines for fader
-        let len = self.fader_knots.len();
-        let mut points_in_screen: Vec<Pos2> = self.fader_knots[0..len - 1]
+        let fader_knots = cut.fader_knots();
+        let len = fader_knots.len();
+        let mut points_in_screen: Vec<Pos2> = fader_knots[0..len - 1]
             .iter()
-            .map(|k| bars_to_screen * k.pos)
+            .map(|k| fader_to_screen * k.pos)
             .collect();
 
         points_in_screen.push(
-            bars_to_screen
-                * if self.looping {
-                    Pos2::new(self.fader_knots[len - 1].pos.x, self.fader_knots[0].pos.y)
+            fader_to_screen
+                * if cut.is_looping() {
+                    Pos2::new(fader_knots[len - 1].pos.x, fader_knots[0].pos.y)
                 } else {
-                    self.fader_knots[len - 1].pos
+                    fader_knots[len - 1].pos
                 },
         );
 
         painter.add(PathShape::line(points_in_screen, config.stroke_fader));
+        painter.line_segment(
+            [fader_rect.left_top(), fader_rect.right_top()],
+            config.stroke_line,
+        );
+
+        // gain of the fader, when shaped by the curve or lag
+        if cut.get_fader_curve() != (FaderCurve::Linear, 0.0) {
+            let fader_step = cut.get_bars() / points;
+            let gain: Vec<Pos2> = (0..=points as usize)
+                .map(|i| {
+                    let t = i as f32 * fader_step;
+                    fader_to_screen * Pos2::new(t, cut.sample_gain(t).unwrap_or(0.0))
+                })
+                .collect();
+            let stroke = Stroke::new(
+                config.stroke_fader.width,
+                config.stroke_fader.color.linear_multiply(0.5),
+            );
+            painter.extend(Shape::dashed_line(&gain, stroke, 4.0, 4.0));
+        }
 
         // cut and fader knots
         painter.extend(cut_knot_shapes);
@@ -1149,18 +782,8 @@ impl Cut {
             self.cursor = Some(pos);
             let t = bars_to_screen.inverse().transform_pos(pos).x;
 
-            if let Some(y) = self.cut_spline.sample(t) {
-                let y = if self.warping {
-                    if y > 1.0 {
-                        y - 1.0
-                    } else if y < 0.0 {
-                        y + 1.0
-                    } else {
-                        y
-                    }
-                } else {
-                    y.clamp(0.0, 1.0)
-                };
+            if let Some(y) = cut.spline_value(t) {
+                let y = cut.wrap_value(y);
                 self.value = Some(y);
             } else {
                 self.value = None;
@@ -1168,20 +791,12 @@ impl Cut {
 
             // println!("cut ratio {:?}", self.value);
 
-            let logic_pos = to_screen.inverse().transform_pos(pos);
-            let segment_pos = logic_pos.x / scale;
-            let round_segment_x = segment_pos.round();
-
-            let logic_x = round_segment_x * scale;
-
+            // grid line knots snap to
+            let x = grid.snap(t);
             let _segment = painter.add(PathShape::line(
                 vec![
-                    to_screen * Pos2 { x: logic_x, y: 0.0 },
-                    to_screen
-                        * Pos2 {
-                            x: logic_x,
-                            y: response.rect.height(),
-                        },
+                    bars_to_screen * Pos2 { x, y: 0.0 },
+                    fader_to_screen * Pos2 { x, y: 1.0 },
                 ],
                 config.stroke_line,
             ));
@@ -1190,131 +805,48 @@ impl Cut {
             self.value = None;
         }
 
+        // playhead
+        if let Some(t) = playhead {
+            painter.add(PathShape::line(
+                vec![
+                    bars_to_screen * Pos2 { x: t, y: 0.0 },
+                    fader_to_screen * Pos2 { x: t, y: 1.0 },
+                ],
+                config.stroke_playhead,
+            ));
+        }
+
         // grid
-        let stroke_grid_16 = Stroke::new(1.0, Color32::GRAY.linear_multiply(0.01));
-        let stroke_grid_4 = Stroke::new(2.0, Color32::GRAY.linear_multiply(0.10));
-        let stroke_grid_1 = Stroke::new(2.0, Color32::GRAY.linear_multiply(0.20));
-        for t in 0..segments as usize {
+        let stroke_grid_16 = Stroke::new(1.0, config.grid_color.linear_multiply(0.01));
+        let stroke_grid_4 = Stroke::new(2.0, config.grid_color.linear_multiply(0.10));
+        let stroke_grid_1 = Stroke::new(2.0, config.grid_color.linear_multiply(0.20));
+        for (i, x) in grid.lines(self.cut.get_bars()) {
             painter.add(PathShape::line(
                 vec![
-                    to_screen
-                        * Pos2 {
-                            x: t as f32 * scale,
-                            y: 0.0,
-                        },
-                    to_screen
-                        * Pos2 {
-                            x: t as f32 * scale,
-                            y: response.rect.height(),
-                        },
+                    bars_to_screen * Pos2 { x, y: 0.0 },
+                    fader_to_screen * Pos2 { x, y: 1.0 },
                 ],
-                if t % (16) == 0 {
-                    stroke_grid_1
-                } else if t % 4 == 0 {
-                    stroke_grid_4
-                } else {
-                    stroke_grid_16
+                match grid.line(i) {
+                    grid::Line::Bar => stroke_grid_1,
+                    grid::Line::Beat => stroke_grid_4,
+                    grid::Line::Division => stroke_grid_16,
                 },
             ));
         }
 
         // store checkpoint
-        if !checkpoint.is_empty() {
-            debug!("store undo checkpoint");
-            self.undo.push(checkpoint);
-        }
+        self.cut.push_checkpoint(checkpoint);
 
         // undo checkpoint
         if ui.input_mut(|i| i.consume_key(Modifiers::CTRL, Key::Z)) {
             debug!("Undo Ctrl-Z");
-            if let Some(check_point) = self.undo.pop() {
-                let mut redo = vec![];
-                check_point
-                    .into_iter()
-                    .for_each(|check_point_data| match check_point_data {
-                        CheckPointData::CutKnots(cut_knots) => {
-                            debug!("undo cut_knots {:?}", cut_knots);
-                            redo.push(CheckPointData::CutKnots(self.cut_knots.clone()));
-                            self.cut_knots = cut_knots;
-                            self.cut_spline_update()
-                        }
-                        CheckPointData::FaderKnots(fader_knots) => {
-                            debug!("undo fader_knots {:?}", fader_knots);
-                            redo.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
-                            self.fader_knots = fader_knots;
-                            self.fader_spline_update();
-                        }
-                        CheckPointData::CutKnot(IndexKnot { index, knot }) => {
-                            debug!("undo cut_knot {:?}", index);
-                            redo.push(CheckPointData::CutKnot(IndexKnot {
-                                index,
-                                knot: self.cut_knots[index],
-                            }));
-                            self.cut_knots[index] = knot;
-                            self.cut_spline_update();
-                        }
-                        CheckPointData::FaderKnot(IndexKnot { index, knot }) => {
-                            debug!("undo fader knot {:?}", index);
-                            redo.push(CheckPointData::FaderKnot(IndexKnot {
-                                index,
-                                knot: self.fader_knots[index],
-                            }));
-                            self.fader_knots[index] = knot;
-                            self.fader_spline_update();
-                        }
-                    });
-                if !redo.is_empty() {
-                    debug!("store redo checkpoint");
-                    self.redo.push(redo);
-                }
-            }
+            self.cut.undo();
         }
 
         // redo checkpoint
         if ui.input_mut(|i| i.consume_key(Modifiers::CTRL | Modifiers::SHIFT, Key::Z)) {
             debug!("Shift Ctrl-Z");
-            // restore checkpoint
-            if let Some(check_point) = self.redo.pop() {
-                let mut undo = vec![];
-                check_point
-                    .into_iter()
-                    .for_each(|check_point_data| match check_point_data {
-                        CheckPointData::CutKnots(cut_knots) => {
-                            debug!("redo cut_knots {:?}", cut_knots);
-                            undo.push(CheckPointData::CutKnots(self.cut_knots.clone()));
-                            self.cut_knots = cut_knots;
-                            self.cut_spline_update()
-                        }
-                        CheckPointData::FaderKnots(fader_knots) => {
-                            debug!("redo fader_knots {:?}", fader_knots);
-                            undo.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
-                            self.fader_knots = fader_knots;
-                            self.fader_spline_update();
-                        }
-                        CheckPointData::CutKnot(IndexKnot { index, knot }) => {
-                            debug!("redo cut knot {:?}", index);
-                            undo.push(CheckPointData::CutKnot(IndexKnot {
-                                index,
-                                knot: self.cut_knots[index],
-                            }));
-                            self.cut_knots[index] = knot;
-                            self.cut_spline_update();
-                        }
-                        CheckPointData::FaderKnot(IndexKnot { index, knot }) => {
-                            debug!("redo fader knot {:?}", index);
-                            undo.push(CheckPointData::FaderKnot(IndexKnot {
-                                index,
-                                knot: self.fader_knots[index],
-                            }));
-                            self.fader_knots[index] = knot;
-                            self.fader_spline_update();
-                        }
-                    });
-                if !undo.is_empty() {
-                    debug!("store redo checkpoint");
-                    self.undo.push(undo);
-                }
-            }
+            self.cut.redo();
         }
 
         response
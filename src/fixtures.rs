@@ -0,0 +1,22 @@
+// fixtures
+//
+// Cuts shared by the unit tests.
+use crate::{
+    model::{Cut, Knot},
+    render::RenderSettings,
+};
+use egui::pos2;
+use std::path::Path;
+
+/// A cut of the sine sample with the fader open, and settings rendering bars of it
+pub fn sine_cut(bars: f32) -> (Cut, RenderSettings) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("audio/sine.wav");
+    let (mut cut, _) = Cut::load_path(path, None).unwrap();
+    let end = cut.get_bars();
+    cut.set_fader_knots(vec![Knot::new(pos2(0.0, 1.0)), Knot::new(pos2(end, 1.0))]);
+    let settings = RenderSettings {
+        bars: Some(bars),
+        ..RenderSettings::default()
+    };
+    (cut, settings)
+}
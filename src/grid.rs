@@ -0,0 +1,183 @@
+// grid
+//
+// The snapping grid of a cut: a number of subdivisions per bar, straight or
+// triplet, and a swing percentage. Swing delays every second subdivision of a
+// straight grid, 50% is straight, 66% is a triplet feel, 75% a dotted feel.
+// Triplet grids are not swung.
+//
+// All positions are in bars, a bar has 4 beats.
+
+/// Beats per bar drawn on the grid
+const BEATS: u32 = 4;
+
+/// Swing range, in percent
+pub const SWING_STRAIGHT: f32 = 50.0;
+pub const SWING_MAX: f32 = 75.0;
+
+/// Quantization choices, in subdivisions per bar
+pub const QUANTIZATIONS: [u32; 7] = [4, 8, 16, 32, 12, 24, 48];
+
+/// Note value of a quantization, e.g. 16 -> "1/16", 24 (triplets) -> "1/16T"
+pub fn quantization_name(quantization: u32) -> String {
+    if quantization.is_multiple_of(3) {
+        format!("1/{}T", quantization / 3 * 2)
+    } else {
+        format!("1/{}", quantization)
+    }
+}
+
+/// Weight of a grid line
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    Bar,
+    Beat,
+    Division,
+}
+
+/// Snapping grid
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Grid {
+    /// Subdivisions per bar, triplet grids are multiples of 3
+    division: u32,
+
+    /// Offset of every second subdivision, 0.5 straight, in subdivision pairs
+    swing: f32,
+}
+
+impl Grid {
+    /// Grid of division per bar with swing in percent
+    pub fn new(division: u32, swing: f32) -> Self {
+        let division = division.max(1);
+        let swing = if division.is_multiple_of(3) {
+            SWING_STRAIGHT
+        } else {
+            swing.clamp(SWING_STRAIGHT, SWING_MAX)
+        };
+        Grid {
+            division,
+            swing: swing / 100.0,
+        }
+    }
+
+    /// Triplet subdivisions
+    pub fn is_triplet(&self) -> bool {
+        self.division.is_multiple_of(3)
+    }
+
+    /// Position in bars of line i
+    pub fn position(&self, i: i64) -> f32 {
+        let pair = i.div_euclid(2) as f32;
+        let offset = if i.rem_euclid(2) == 1 {
+            self.swing
+        } else {
+            0.0
+        };
+        2.0 * (pair + offset) / self.division as f32
+    }
+
+    /// Weight of line i
+    pub fn line(&self, i: i64) -> Line {
+        let division = self.division as i64;
+        if i.rem_euclid(division) == 0 {
+            Line::Bar
+        } else if (i * BEATS as i64).rem_euclid(division) == 0 {
+            Line::Beat
+        } else {
+            Line::Division
+        }
+    }
+
+    /// Index of the grid line closest to x (in bars)
+    pub fn nearest(&self, x: f32) -> i64 {
+        let pair = (x * self.division as f32 / 2.0).floor() as i64;
+        [2 * pair, 2 * pair + 1, 2 * pair + 2]
+            .into_iter()
+            .min_by(|a, b| {
+                let da = (self.position(*a) - x).abs();
+                let db = (self.position(*b) - x).abs();
+                da.total_cmp(&db)
+            })
+            .unwrap_or(2 * pair)
+    }
+
+    /// Round x (in bars) to the closest grid line
+    pub fn snap(&self, x: f32) -> f32 {
+        self.position(self.nearest(x))
+    }
+
+    /// Lines from 0 up to and including bars, with their index
+    pub fn lines(&self, bars: f32) -> impl Iterator<Item = (i64, f32)> + '_ {
+        let last = (bars * self.division as f32).ceil() as i64;
+        (0..=last)
+            .map(|i| (i, self.position(i)))
+            .filter(move |(_, x)| *x <= bars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swing() {
+        // eighths, the off-beats at 0.125 straight
+        let straight = Grid::new(8, SWING_STRAIGHT);
+        assert_eq!(straight.snap(0.1), 0.125);
+        assert_eq!(straight.snap(0.2), 0.25);
+
+        // shifted to 60% and 75% of the pair, the beats stay
+        let grid = Grid::new(8, 60.0);
+        assert_eq!(grid.position(1), 2.0 * 0.6 / 8.0);
+        assert_eq!(grid.snap(0.16), grid.position(1));
+        assert_eq!(grid.snap(0.26), 0.25);
+        let grid = Grid::new(8, 75.0);
+        assert_eq!(grid.snap(0.1), 0.1875);
+        assert_eq!(grid.snap(0.2), 0.1875);
+        assert_eq!(grid.snap(0.05), 0.0);
+        assert_eq!(grid.snap(0.23), 0.25);
+        assert_eq!(grid.snap(0.45), 0.4375);
+
+        let off_beats: Vec<f32> = grid.lines(1.0).skip(1).step_by(2).map(|(_, x)| x).collect();
+        assert_eq!(off_beats, [0.1875, 0.4375, 0.6875, 0.9375]);
+
+        // clamped to the swing range
+        assert_eq!(Grid::new(8, 90.0), grid);
+        assert_eq!(Grid::new(8, 0.0), straight);
+    }
+
+    #[test]
+    fn triplets() {
+        for division in [12, 24, 48] {
+            // not swung
+            let grid = Grid::new(division, 66.0);
+            assert!(grid.is_triplet());
+            assert_eq!(grid, Grid::new(division, SWING_STRAIGHT));
+
+            // a multiple of 3 steps per beat, every line found again
+            let n = division as i64;
+            let steps = n / BEATS as i64;
+            assert_eq!(steps % 3, 0);
+            assert_eq!(grid.lines(1.0).count(), division as usize + 1);
+            for i in -n..=2 * n {
+                assert_eq!(grid.nearest(grid.position(i)), i);
+                let line = if i % n == 0 {
+                    Line::Bar
+                } else if i % steps == 0 {
+                    Line::Beat
+                } else {
+                    Line::Division
+                };
+                assert_eq!(grid.line(i), line);
+            }
+        }
+
+        let grid = Grid::new(12, SWING_STRAIGHT);
+        assert_eq!(grid.snap(0.3), 1.0 / 3.0);
+        assert_eq!(grid.position(4), 1.0 / 3.0);
+        // 1/8 triplets, 3 steps per beat
+        assert_eq!(grid.position(3), 0.25);
+        assert_eq!(grid.line(3), Line::Beat);
+        assert_eq!(quantization_name(12), "1/8T");
+        assert_eq!(quantization_name(16), "1/16");
+    }
+}
@@ -1,4 +1,15 @@
 pub mod config;
+pub mod cut_file;
 pub mod cut_panel;
+pub mod grid;
+pub mod model;
+pub mod patterns;
+pub mod playback;
+pub mod render;
+pub mod session;
 pub mod sinc;
+pub mod wav_data;
 pub mod wav_panel;
+
+#[cfg(test)]
+mod fixtures;
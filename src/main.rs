@@ -1,15 +1,21 @@
 // #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use std::fs::File;
-use std::io::prelude::*;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use cut_creator::{
-    config::Config,
-    cut_panel::{Cut, OptCut},
+    config::{self, Config},
+    cut_file,
+    cut_panel::{CutPanel, OptCut},
+    model::Cut,
+    playback::{self, Player},
+    session::{self, Session, SESSION_EXTENSION},
 };
 
 use egui::*;
-use log::{debug, info};
+use log::{debug, error, info};
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
@@ -24,23 +30,14 @@ fn main() -> Result<(), eframe::Error> {
         ..Default::default()
     };
 
-    let mut app = Box::<App>::default();
-    // load config
-    if let Ok(mut file) = File::open("config.json") {
-        let mut json = String::new();
-        file.read_to_string(&mut json).unwrap();
-        debug!("config json {}", json);
-        app.config = serde_json::from_str(&json).unwrap();
-    }
-
     eframe::run_native(
         "Cut Creator",
         options,
-        Box::new(|_cc| Ok(Box::new(App::new(_cc)))),
+        Box::new(|cc| Ok(Box::new(App::new(cc, config::config_path())))),
     )
 }
 
-const NR_EDITORS: usize = 10;
+const NR_EDITORS: usize = session::NR_LANES;
 #[derive(Default)]
 struct App {
     /// Enable/disable each cut slots
@@ -51,30 +48,70 @@ struct App {
     cur_cut: usize,
     /// Top level configuration
     config: Config,
+    /// Config file, written on exit
+    config_path: PathBuf,
+    /// The config file could not be read, it is kept as backup on exit
+    config_error: bool,
+    /// Settings window open
+    show_settings: bool,
     /// Allowed to close (depending on save state)
     allowed_to_close: bool,
     /// Set if confirmation dialogue already open
     show_confirmation_dialog: bool,
     /// Status string at bottom
     status: String,
+    /// Audition, opened on first play
+    player: Option<Player>,
+    /// Index of the cut played, and its revision when rendered
+    playing: Option<(usize, u64)>,
+    /// Loop playback
+    loop_playback: bool,
+    /// Slots, lane mix settings and mixdown
+    session: Session,
+    /// Session file, if opened or saved
+    session_path: Option<PathBuf>,
+    /// Set the panel widths from the session on the next frame
+    apply_layout: bool,
 }
 
 impl App {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, config_path: PathBuf) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
         // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
-        Self::default()
+        info!("config {}", config_path.display());
+        let (config, config_error) = match Config::load(&config_path) {
+            Ok(config) => (config, None),
+            Err(err) => {
+                error!("{}", err);
+                (Config::default(), Some(err))
+            }
+        };
+        let mut app = Self {
+            config,
+            config_path,
+            config_error: config_error.is_some(),
+            ..Self::default()
+        };
+        cc.egui_ctx.set_visuals(app.config.theme.visuals());
+        // restore the last session
+        if let Some(path) = app.config.last_session.clone() {
+            app.open_session(&path);
+        }
+        if let Some(err) = config_error {
+            app.status = format!("{}, using the default config", err);
+        }
+        app
     }
 
     /// load file
     fn load_file(&mut self, i: usize) {
         debug!("load_file cut #{}", i);
-        self.status = match Cut::load_file() {
-            Ok((cut, msg)) => {
-                let path = cut.cut_path.clone();
-                self.cuts[self.cur_cut] = OptCut(Some(cut));
+        self.status = match CutPanel::load_file(self.config.sample_rate) {
+            Ok((panel, msg)) => {
+                let path = panel.cut.cut_path.clone();
+                self.cuts[self.cur_cut] = OptCut(Some(panel));
                 self.enabled[i] = true;
                 format!("File loaded {}, ({})", path.to_string_lossy(), msg)
             }
@@ -82,6 +119,227 @@ impl App {
         };
     }
 
+    /// set project sample rate, loaded samples are converted
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        debug!("set_sample_rate {}", sample_rate);
+        self.config.sample_rate = sample_rate;
+        self.status = format!("Sample rate {} Hz", sample_rate);
+        for panel in self
+            .cuts
+            .iter_mut()
+            .filter_map(|opt_cut| opt_cut.0.as_mut())
+        {
+            if let Err(err) = panel.cut.set_project_rate(sample_rate) {
+                self.status = err.to_string();
+            }
+        }
+    }
+
+    /// snapshot of cut i, rendered by the player
+    fn playback_cut(&self, i: usize) -> Option<Arc<Cut>> {
+        let panel = self.cuts[i].0.as_ref()?;
+        Some(Arc::new(panel.cut.snapshot()))
+    }
+
+    /// play the current cut from the start
+    fn play(&mut self) {
+        if self.player.is_none() {
+            let (sink, msg) = playback::default_sink(self.config.sample_rate);
+            self.status = msg;
+            self.player = Some(Player::new(sink));
+        }
+        let i = self.cur_cut;
+        let (Some(player), Some(cut)) = (&self.player, self.playback_cut(i)) else {
+            self.status = "No cut to play".to_string();
+            return;
+        };
+        player.set_looping(self.loop_playback);
+        let revision = cut.revision();
+        match player.play(cut.clone(), &cut.render) {
+            Ok(()) => self.playing = Some((i, revision)),
+            Err(err) => self.status = err,
+        }
+    }
+
+    /// loop playback
+    fn set_loop_playback(&mut self, loop_playback: bool) {
+        self.loop_playback = loop_playback;
+        if let Some(player) = &self.player {
+            player.set_looping(loop_playback);
+        }
+    }
+
+    /// stop playing
+    fn stop(&mut self) {
+        if let Some(player) = &self.player {
+            player.stop();
+        }
+        self.playing = None;
+    }
+
+    /// follow the player, swap in a snapshot of the played cut when edited
+    fn update_playback(&mut self) {
+        let Some(player) = &self.player else {
+            return;
+        };
+        if let Some(err) = player.take_error() {
+            self.status = err;
+        }
+        let Some((i, revision)) = self.playing else {
+            return;
+        };
+        if !player.is_playing() {
+            self.playing = None;
+            return;
+        }
+        let Some(panel) = &self.cuts[i].0 else {
+            self.stop();
+            return;
+        };
+        let current = panel.cut.revision();
+        if current != revision {
+            if let Some(cut) = self.playback_cut(i) {
+                if let Err(err) = player.update(cut.clone(), &cut.render) {
+                    self.status = err;
+                }
+            }
+            self.playing = Some((i, current));
+        }
+    }
+
+    /// playhead of cut i, in bars and as value in the wav window
+    fn playhead(&self, i: usize) -> Option<(f32, f32)> {
+        let (playing, _) = self.playing?;
+        let panel = self.cuts[i].0.as_ref()?;
+        let position = self.player.as_ref()?.position()?;
+        if playing != i {
+            return None;
+        }
+        let cut = &panel.cut;
+        let t = position * cut.render.bars.unwrap_or(cut.get_bars());
+        let value = cut.wrap_value(cut.sample_spline(t)?);
+        Some((t, value))
+    }
+
+    /// session file, the mixdown is named after it
+    fn session_path(&self) -> PathBuf {
+        self.session_path
+            .clone()
+            .unwrap_or(PathBuf::from("mixdown"))
+    }
+
+    /// open the session at path, replacing the cuts in all slots
+    fn open_session(&mut self, path: &Path) {
+        debug!("open_session {:?}", path);
+        if self.unsaved_cuts() > 0 {
+            self.status = "Save the cuts before opening a session".to_string();
+            return;
+        }
+        let session = match Session::load(path) {
+            Ok(session) => session,
+            Err(err) => {
+                self.status = err;
+                return;
+            }
+        };
+        self.stop();
+
+        let mut errors = vec![];
+        for (i, slot) in session.slots.iter().enumerate() {
+            self.cuts[i] = match &slot.cut_path {
+                Some(cut_path) => match CutPanel::open(cut_path.clone(), self.config.sample_rate) {
+                    Ok((panel, _)) => OptCut(Some(panel)),
+                    Err(err) => {
+                        errors.push(format!("#{}: {}", i, err));
+                        OptCut(None)
+                    }
+                },
+                None => OptCut(None),
+            };
+            self.enabled[i] = slot.enabled;
+        }
+        self.cur_cut = session.current;
+        self.session = session;
+        self.session_path = Some(path.to_path_buf());
+        self.config.last_session = Some(path.to_path_buf());
+        self.apply_layout = true;
+
+        self.status = format!("Session opened {}", path.display());
+        if !errors.is_empty() {
+            self.status = format!("{}, {}", self.status, errors.join(", "));
+        }
+    }
+
+    /// save the session to path, the cuts are saved separately
+    fn save_session(&mut self, path: &Path) {
+        debug!("save_session {:?}", path);
+        for (i, slot) in self.session.slots.iter_mut().enumerate() {
+            slot.cut_path = self.cuts[i]
+                .0
+                .as_ref()
+                .map(|panel| panel.cut.cut_path.clone());
+            slot.enabled = self.enabled[i];
+        }
+        self.session.current = self.cur_cut;
+
+        self.status = match self.session.save(path) {
+            Ok(()) => {
+                self.session_path = Some(path.to_path_buf());
+                self.config.last_session = Some(path.to_path_buf());
+                match self.unsaved_cuts() {
+                    0 => format!("Session saved {}", path.display()),
+                    n => format!("Session saved {}, {} cuts not saved", path.display(), n),
+                }
+            }
+            Err(err) => err,
+        };
+    }
+
+    /// pick a session to open
+    fn open_session_dialog(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("session", &[SESSION_EXTENSION])
+            .pick_file()
+        {
+            self.open_session(&path);
+        }
+    }
+
+    /// save to the session file, or pick one if there is none
+    fn save_session_dialog(&mut self, save_as: bool) {
+        let path = match &self.session_path {
+            Some(path) if !save_as => Some(path.clone()),
+            _ => rfd::FileDialog::new()
+                .add_filter("session", &[SESSION_EXTENSION])
+                .set_file_name(format!("session.{}", SESSION_EXTENSION))
+                .save_file(),
+        };
+        if let Some(path) = path {
+            self.save_session(&path);
+        }
+    }
+
+    /// number of cuts with unsaved changes
+    fn unsaved_cuts(&self) -> usize {
+        self.cuts
+            .iter()
+            .filter_map(|opt_cut| opt_cut.0.as_ref())
+            .filter(|panel| panel.cut.needs_save())
+            .count()
+    }
+
+    /// mix the audible lanes to file
+    fn mixdown(&mut self) {
+        let cuts = std::array::from_fn(|i| self.cuts[i].0.as_ref().map(|panel| &panel.cut));
+        self.status = match self
+            .session
+            .mixdown(&self.session_path(), cuts, &self.enabled)
+        {
+            Ok(paths) => format!("Mixdown rendered to {:?}", paths),
+            Err(err) => err,
+        };
+    }
+
     /// clear all cuts but i
     fn clear_cuts(&mut self, i: usize) {
         debug!("clear_cuts all but {}", i);
@@ -97,26 +355,42 @@ impl eframe::App for App {
     /// on exit
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
         debug!("exit");
-        // Serialize it to a JSON string.
-        let json = serde_json::to_string(&self.config).unwrap();
-        debug!("json config {}", json);
-
-        let mut file = File::create("config.json").unwrap();
-        file.write_all(json.as_bytes()).unwrap();
+        // keep the config that could not be read
+        if self.config_error {
+            let backup = self.config_path.with_extension("json.bak");
+            if let Err(err) = std::fs::copy(&self.config_path, &backup) {
+                error!("backup config to {}: {}", backup.display(), err);
+            }
+        }
+        if let Err(err) = self.config.save(&self.config_path) {
+            error!("{}", err);
+        }
     }
 
     /// update
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // playback, space to play/stop
+        if !ctx.wants_keyboard_input()
+            && ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Space))
+        {
+            if self.playing.is_some() {
+                self.stop();
+            } else {
+                self.play();
+            }
+        }
+        self.update_playback();
+        if self.playing.is_some() {
+            ctx.request_repaint();
+        }
+        let playheads: Vec<Option<(f32, f32)>> =
+            (0..NR_EDITORS).map(|i| self.playhead(i)).collect();
+
         // close?
         if ctx.input(|i| i.viewport().close_requested())
             && !self.allowed_to_close // not allowed to close
-            && self.cuts.iter().any(|opt_cut| { // unsaved work?
-                if let Some(ref cut) = opt_cut.0 {
-                    cut.needs_save()
-                } else {
-                    false
-                }
-            })
+            && self.unsaved_cuts() > 0
+        // unsaved work?
         {
             // we hav unsaved cuts, so cancel the close event
             ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
@@ -145,10 +419,38 @@ impl eframe::App for App {
                     });
                 });
         }
+        // settings window, previewed while editing
+        let mut show_settings = self.show_settings;
+        egui::Window::new("Settings")
+            .open(&mut show_settings)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if self.config.ui_content(ui) {
+                    ctx.set_visuals(self.config.theme.visuals());
+                }
+            });
+        self.show_settings = show_settings;
+
+        // session shortcuts, Ctrl-O and Ctrl-(Shift-)S are taken by the cut
+        if ctx.input_mut(|i| i.consume_key(Modifiers::CTRL | Modifiers::ALT, Key::O)) {
+            self.open_session_dialog();
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::CTRL | Modifiers::ALT, Key::S)) {
+            self.save_session_dialog(false);
+        }
+
+        // panel widths of an opened session
+        let apply_layout = std::mem::take(&mut self.apply_layout);
+        let layout = self.session.layout.clone();
+
         egui::CentralPanel::default().show(ctx, |_ui| {
             // left side panel
-            egui::SidePanel::left("left_id").show(ctx, |ui| {
-                ui.vertical(|ui| {
+            let mut left_panel = egui::SidePanel::left("left_id");
+            if let (true, Some(width)) = (apply_layout, layout.settings_width) {
+                left_panel = left_panel.exact_width(width);
+            }
+            let left_response = left_panel.show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
                     // keyboard events
 
                     if !ctx.wants_keyboard_input() {
@@ -180,7 +482,12 @@ impl eframe::App for App {
                         });
                     }
 
-                    ui.label("Settings");
+                    ui.horizontal(|ui| {
+                        ui.label("Settings");
+                        if ui.button("Settings...").clicked() {
+                            self.show_settings ^= true;
+                        }
+                    });
                     ui.add_space(10.0);
                     ui.checkbox(&mut self.config.knot_line, "knot lines");
 
@@ -192,6 +499,25 @@ impl eframe::App for App {
                         }
                     });
 
+                    let mut sample_rate = self.config.sample_rate;
+                    ui.horizontal(|ui| {
+                        ui.label("Sample Rate");
+                        ComboBox::from_id_source("project_sample_rate")
+                            .selected_text(format!("{}", sample_rate))
+                            .show_ui(ui, |ui| {
+                                for rate in [44100, 48000, 88200, 96000] {
+                                    ui.selectable_value(
+                                        &mut sample_rate,
+                                        rate,
+                                        format!("{}", rate),
+                                    );
+                                }
+                            });
+                    });
+                    if sample_rate != self.config.sample_rate {
+                        self.set_sample_rate(sample_rate);
+                    }
+
                     ui.separator();
 
                     ui.label("Selected Cuts");
@@ -215,7 +541,21 @@ impl eframe::App for App {
                         // each cut has a corresponding button
                         let button = ui.selectable_label(
                             self.enabled[i],
-                            format!("#{}: {}", i, opt_cut.get_name()),
+                            format!(
+                                "#{}: {}{}{}",
+                                i,
+                                opt_cut.get_name(),
+                                if self.session.lanes[i].mute {
+                                    " [M]"
+                                } else {
+                                    ""
+                                },
+                                if self.session.lanes[i].solo {
+                                    " [S]"
+                                } else {
+                                    ""
+                                },
+                            ),
                         );
 
                         // check hover
@@ -255,13 +595,43 @@ impl eframe::App for App {
                     ui.label("Cut Settings");
                     ui.add_space(10.0);
 
-                    if let Some(cut) = &mut self.cuts[self.cur_cut].0 {
+                    if let Some(panel) = &mut self.cuts[self.cur_cut].0 {
+                        let cut = &mut panel.cut;
                         cut.wav.ui_content_ctrl(ui, &cut.wav_data, self.cur_cut);
 
-                        cut.ui_content_settings(ui, &mut self.status);
+                        panel.ui_content_settings(ui, &mut self.status);
+                    }
+
+                    ui.separator();
+                    ui.label(format!("Mix #{}", self.cur_cut));
+                    ui.add_space(10.0);
+                    self.session.lanes[self.cur_cut].ui_content(ui);
+
+                    ui.separator();
+                    ui.label("Session");
+                    ui.add_space(10.0);
+                    if let Some(path) = &self.session_path {
+                        ui.label(cut_file::file_name(path).unwrap_or_default());
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Open Session...").clicked() {
+                            self.open_session_dialog();
+                        }
+                        if ui.button("Save Session").clicked() {
+                            self.save_session_dialog(false);
+                        }
+                        if ui.button("Save As...").clicked() {
+                            self.save_session_dialog(true);
+                        }
+                    });
+                    let session_path = self.session_path();
+                    self.session.ui_content(ui, &session_path);
+                    if ui.button("Mixdown").clicked() {
+                        self.mixdown();
                     }
                 });
             });
+            self.session.layout.settings_width = Some(left_response.response.rect.width());
 
             // center panel
             egui::CentralPanel::default().show(ctx, |ui| {
@@ -280,18 +650,32 @@ impl eframe::App for App {
                     )
                     .show(ctx, |ui| {
                         let top_panel_info = match self.cuts[self.cur_cut].0 {
-                            Some(ref cut) => {
+                            Some(ref panel) => {
                                 format!(
                                     "#{}: {}, Undo #{:5}, Redo #{}",
                                     self.cur_cut,
-                                    cut.get_name(),
-                                    cut.get_undo_len(),
-                                    cut.get_redo_len()
+                                    panel.cut.get_name(),
+                                    panel.cut.get_undo_len(),
+                                    panel.cut.get_redo_len()
                                 )
                             }
                             _ => "".to_string(),
                         };
-                        ui.label(top_panel_info);
+                        ui.horizontal(|ui| {
+                            // transport
+                            if self.playing.is_some() {
+                                if ui.button("Stop").clicked() {
+                                    self.stop();
+                                }
+                            } else if ui.button("Play").clicked() {
+                                self.play();
+                            }
+                            let mut loop_playback = self.loop_playback;
+                            if ui.checkbox(&mut loop_playback, "loop").clicked() {
+                                self.set_loop_playback(loop_playback);
+                            }
+                            ui.label(top_panel_info);
+                        });
                     });
 
                 // dummy bottom
@@ -320,36 +704,43 @@ impl eframe::App for App {
                     // );
 
                     // right side panel with wav
-                    egui::SidePanel::right("right")
-                        .frame(
-                            Frame::default()
-                                .outer_margin(egui::Margin::same(0.0))
-                                .inner_margin(egui::Margin::same(0.0)),
-                        )
-                        .show(ctx, |ui| {
-                            // right wave panel
-                            for (i, enabled) in self.enabled.iter().enumerate() {
-                                let opt_cut = &mut self.cuts[i];
-
-                                if *enabled {
-                                    egui::Frame::canvas(ui.style())
-                                        .outer_margin(egui::Margin::same(3.0))
-                                        .inner_margin(egui::Margin::same(0.0))
-                                        .show(ui, |ui| {
-                                            if let Some(ref mut cut) = opt_cut.0 {
-                                                cut.wav.ui_content(
-                                                    ui,
-                                                    cut.get_cursor(),
-                                                    cut.get_value(),
-                                                    &cut.wav_data,
-                                                    &self.config,
-                                                    cut_height,
-                                                );
-                                            }
-                                        });
-                                }
+                    let mut right_panel = egui::SidePanel::right("right").frame(
+                        Frame::default()
+                            .outer_margin(egui::Margin::same(0.0))
+                            .inner_margin(egui::Margin::same(0.0)),
+                    );
+                    if let (true, Some(width)) = (apply_layout, layout.wave_width) {
+                        right_panel = right_panel.exact_width(width);
+                    }
+                    let right_response = right_panel.show(ctx, |ui| {
+                        // right wave panel
+                        for (i, enabled) in self.enabled.iter().enumerate() {
+                            let opt_cut = &mut self.cuts[i];
+
+                            if *enabled {
+                                egui::Frame::canvas(ui.style())
+                                    .outer_margin(egui::Margin::same(3.0))
+                                    .inner_margin(egui::Margin::same(0.0))
+                                    .show(ui, |ui| {
+                                        if let Some(ref mut panel) = opt_cut.0 {
+                                            let (cursor, value) =
+                                                (panel.get_cursor(), panel.get_value());
+                                            let cut = &mut panel.cut;
+                                            cut.wav.ui_content(
+                                                ui,
+                                                cursor,
+                                                value,
+                                                playheads[i].map(|(_, value)| value),
+                                                &cut.wav_data,
+                                                &self.config,
+                                                cut_height,
+                                            );
+                                        }
+                                    });
                             }
-                        });
+                        }
+                    });
+                    self.session.layout.wave_width = Some(right_response.response.rect.width());
 
                     // the cut panel
                     egui::CentralPanel::default()
@@ -367,8 +758,13 @@ impl eframe::App for App {
                                         .outer_margin(egui::Margin::same(3.0))
                                         .inner_margin(egui::Margin::same(0.0))
                                         .show(ui, |ui| {
-                                            if let Some(ref mut cut) = opt_cut.0 {
-                                                cut.ui_content(ui, &self.config, cut_height);
+                                            if let Some(ref mut panel) = opt_cut.0 {
+                                                panel.ui_content(
+                                                    ui,
+                                                    &self.config,
+                                                    cut_height,
+                                                    playheads[i].map(|(t, _)| t),
+                                                );
                                             }
                                         });
                                 }
@@ -0,0 +1,2094 @@
+use crate::{
+    cut_file::{self, CutError},
+    grid::{Grid, SWING_STRAIGHT},
+    render::RenderSettings,
+    wav_data::{Wav, WavData, SAMPLE_EXTENSIONS},
+};
+use egui::{pos2, Pos2, Vec2};
+use splines::Spline;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::prelude::*,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, trace};
+/// model
+///
+/// A cut is defined by a spline, CatmullRom interpolation unless set per knot.
+/// Optionally Catmull-Rom segments are replaced by a monotone cubic, which
+/// does not overshoot the knots. Knots with tangent handles shape the
+/// segments next to them as cubic Beziers.
+/// Left and right knots are outside of the cut region by 1/4 bar.
+/// Second left endpoint defines S the start position of the sample.
+/// Second right endpoint defines E the end position of the sample.
+///
+/// The loop option forces S <-> E, which ensures that the cut can be smoothly looped.
+///
+/// The fader is defined by a linear spline, 0.0 closed, 1.0 open. Its value is
+/// converted to gain by the fader curve, after the cut-in lag.
+///
+/// The model holds no UI state besides knot selection, editing operations
+/// return the undo checkpoint data they produce.
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct Knot {
+    /// x position in terms of bars. 0.25 -> 1st quarter in 1st bar
+    /// y position in terms of relative sample position 0.0 beginning of sample 1.0 end of sample.
+    pub pos: Pos2,
+
+    /// Interpolation towards the next knot, cut knots only
+    #[serde(default, skip_serializing_if = "Interpolation::is_default")]
+    pub interpolation: Interpolation,
+
+    /// Tangent handles, cut knots only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handles: Option<Handles>,
+
+    /// Run-time only data
+    #[serde(skip)]
+    pub selected: bool,
+}
+
+impl Knot {
+    pub fn new(pos: Pos2) -> Self {
+        Knot {
+            pos,
+            interpolation: Interpolation::default(),
+            handles: None,
+            selected: false,
+        }
+    }
+}
+
+/// Tangent handles of a knot, y offsets of the Bezier control points at 1/3
+/// of the segment before and after the knot
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct Handles {
+    pub left: f32,
+    pub right: f32,
+}
+
+/// Gain of the fader position, as the curve of a mixer crossfader
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub enum FaderCurve {
+    /// Fully open as soon as the fader passes the lag
+    Sharp,
+    #[default]
+    Linear,
+    /// Equal power, sin of the position
+    ConstantPower,
+    /// Position to the power of the exponent
+    Exponent(f32),
+}
+
+impl FaderCurve {
+    pub const ALL: [FaderCurve; 4] = [
+        FaderCurve::Sharp,
+        FaderCurve::Linear,
+        FaderCurve::ConstantPower,
+        FaderCurve::Exponent(2.0),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaderCurve::Sharp => "sharp",
+            FaderCurve::Linear => "linear",
+            FaderCurve::ConstantPower => "constant power",
+            FaderCurve::Exponent(_) => "exponent",
+        }
+    }
+
+    /// Gain of position x in 0.0..=1.0
+    pub fn gain(&self, x: f32) -> f32 {
+        match self {
+            FaderCurve::Sharp => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            FaderCurve::Linear => x,
+            FaderCurve::ConstantPower => (x * std::f32::consts::FRAC_PI_2).sin(),
+            FaderCurve::Exponent(exponent) => x.powf(*exponent),
+        }
+    }
+}
+
+/// Interpolation from a cut knot to the next
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    #[default]
+    CatmullRom,
+    Linear,
+    /// Hold the value, jump at the next knot
+    Step,
+    Cosine,
+    /// Ease out of the knot, and into the next if it is Bezier too
+    Bezier,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 5] = [
+        Interpolation::CatmullRom,
+        Interpolation::Linear,
+        Interpolation::Step,
+        Interpolation::Cosine,
+        Interpolation::Bezier,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Interpolation::CatmullRom => "catmull-rom",
+            Interpolation::Linear => "linear",
+            Interpolation::Step => "step",
+            Interpolation::Cosine => "cosine",
+            Interpolation::Bezier => "bezier",
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Interpolation::default()
+    }
+
+    /// Spline interpolation of a key with value y
+    fn key(&self, y: f32) -> splines::Interpolation<f32, f32> {
+        match self {
+            Interpolation::CatmullRom => splines::Interpolation::CatmullRom,
+            Interpolation::Linear => splines::Interpolation::Linear,
+            Interpolation::Step => splines::Interpolation::Step(1.0),
+            Interpolation::Cosine => splines::Interpolation::Cosine,
+            // control point at the knot, flat tangent
+            Interpolation::Bezier => splines::Interpolation::Bezier(y),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CheckPointData {
+    CutKnots(Vec<Knot>),
+    FaderKnots(Vec<Knot>),
+    CutKnot(IndexKnot),
+    FaderKnot(IndexKnot),
+    Bars(f32),
+}
+
+/// How the knots follow a change of the cut length
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Resize {
+    /// Scale all knot positions
+    #[default]
+    Stretch,
+    /// Keep the knots, extend or trim at the end
+    ExtendTrim,
+}
+
+impl Resize {
+    pub const ALL: [Resize; 2] = [Resize::Stretch, Resize::ExtendTrim];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resize::Stretch => "stretch",
+            Resize::ExtendTrim => "extend/trim",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct IndexKnot {
+    pub index: usize,
+    pub knot: Knot,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Cut {
+    /// File format version, see `cut_file`
+    #[serde(default)]
+    version: u32,
+
+    /// path to the cut, set on load
+    #[serde(default)]
+    pub cut_path: PathBuf,
+
+    /// path to the sample, stored relative to the cut when possible
+    pub sample_path: Option<PathBuf>,
+
+    /// size of the sample file, for relinking
+    #[serde(default)]
+    sample_size: Option<u64>,
+
+    /// hash of the sample file, for relinking
+    #[serde(default)]
+    sample_hash: Option<u64>,
+
+    /// Quantization 4 -> 1/4 = 0.25 (quarter notes), 16-> 1/16 (six teens), etc.
+    quantization: u32,
+
+    /// Swing of the grid in percent, 50 is straight
+    #[serde(default = "default_swing")]
+    swing: f32,
+
+    /// Length in terms of bars, e.g. 1.0 amounts to 4 quarter notes, etc.
+    bars: f32,
+
+    /// The control points.
+    cut_knots: Vec<Knot>,
+
+    /// Fader
+    fader_knots: Vec<Knot>,
+
+    /// Looping, the end point equates the start point
+    looping: bool,
+
+    /// Warping, the samples will warp across start/end
+    warping: bool,
+
+    /// Monotone cubic instead of Catmull-Rom, no overshoot between knots
+    #[serde(default)]
+    monotone: bool,
+
+    /// Gain curve of the fader
+    #[serde(default)]
+    fader_curve: FaderCurve,
+
+    /// Cut-in lag, fader travel 0.0..=0.5 before the sound starts
+    #[serde(default)]
+    fader_lag: f32,
+
+    /// Wav
+    pub wav: Wav,
+
+    /// Render settings
+    #[serde(default)]
+    pub render: RenderSettings,
+
+    /// Run-time only data
+
+    /// Changed since last save, besides knots and wav
+    #[serde(skip)]
+    changed: bool,
+
+    /// Undo stack
+    #[serde(skip)]
+    undo: Vec<Vec<CheckPointData>>,
+
+    #[serde(skip)]
+    redo: Vec<Vec<CheckPointData>>,
+
+    /// Cut Spline
+    #[serde(skip)]
+    cut_spline: Spline<f32, f32>,
+
+    /// Fader Spline
+    #[serde(skip)]
+    fader_spline: Spline<f32, f32>,
+
+    /// Monotone tangents at the cut spline keys
+    #[serde(skip)]
+    cut_tangents: Vec<f32>,
+
+    /// Wav Data
+    #[serde(skip)]
+    pub wav_data: WavData,
+
+    /// Candidates for a missing sample
+    #[serde(skip)]
+    pub relink_candidates: Vec<PathBuf>,
+
+    /// Sample rate samples are converted to, None keeps the rate of the file
+    #[serde(skip)]
+    project_rate: Option<u32>,
+
+    /// Bumped on every change that may change the rendered audio
+    #[serde(skip)]
+    revision: u64,
+}
+
+fn default_swing() -> f32 {
+    SWING_STRAIGHT
+}
+
+impl Default for Cut {
+    fn default() -> Self {
+        let cut_knots = vec![
+            // start top left
+            Knot::new(pos2(-0.25, 0.0)),
+            Knot::new(pos2(0.0, 0.0)),
+            Knot::new(pos2(1.0, 0.5)),
+            Knot::new(pos2(2.0, 1.0)),
+            // end top right
+            Knot::new(pos2(2.25, 1.0)),
+        ];
+
+        let fader_knots = vec![
+            Knot::new(pos2(0.0, 0.0)),
+            Knot::new(pos2(1.0, 0.0)),
+            Knot::new(pos2(1.0, 1.0)),
+            Knot::new(pos2(1.5, 1.0)),
+            Knot::new(pos2(1.5, 0.0)),
+            Knot::new(pos2(2.0, 0.0)),
+        ];
+
+        let bars = 2.0;
+        let quantization = 16;
+
+        let mut cut = Self {
+            version: cut_file::VERSION,
+            cut_path: PathBuf::new(),
+            sample_path: None,
+            sample_size: None,
+            sample_hash: None,
+            quantization,
+            swing: SWING_STRAIGHT,
+            bars,
+            cut_knots,
+            fader_knots,
+            looping: false,
+            warping: false,
+            monotone: false,
+            fader_curve: FaderCurve::default(),
+            fader_lag: 0.0,
+            wav: Wav::default(),
+            render: RenderSettings::default(),
+
+            // Non persistent data
+            changed: false,
+            undo: vec![],
+            redo: vec![],
+            cut_spline: Spline::from_vec(vec![]),
+            fader_spline: Spline::from_vec(vec![]),
+            cut_tangents: vec![],
+            wav_data: WavData::default(),
+            relink_candidates: vec![],
+            project_rate: None,
+            revision: 0,
+        };
+        cut.cut_spline_update();
+        cut.fader_spline_update();
+        cut
+    }
+}
+
+impl Cut {
+    // Sample spline in bars
+    pub fn sample_spline(&self, bar_pos: f32) -> Option<f32> {
+        let keys = self.cut_spline.keys();
+        let t = bar_pos.clamp(keys.first()?.t, keys.last()?.t);
+        self.custom_value(t)
+            .or_else(|| self.cut_spline.clamped_sample(bar_pos))
+    }
+
+    // Sample fader spline in bars, 0.0 closed, 1.0 open
+    pub fn sample_fader(&self, bar_pos: f32) -> Option<f32> {
+        match self.fader_spline.clamped_sample(bar_pos) {
+            // knots sharing the same x (a hard cut) normalize to 0/0, use the rightmost
+            Some(v) if v.is_nan() => self
+                .fader_spline
+                .keys()
+                .iter()
+                .rev()
+                .find(|k| k.t == bar_pos)
+                .map(|k| k.value),
+            v => v,
+        }
+    }
+
+    /// Gain of the fader in bars, the fader value through the lag and curve
+    pub fn sample_gain(&self, bar_pos: f32) -> Option<f32> {
+        self.sample_fader(bar_pos).map(|y| self.fader_gain(y))
+    }
+
+    /// Gain of fader value y
+    pub fn fader_gain(&self, y: f32) -> f32 {
+        let x = ((y - self.fader_lag) / (1.0 - self.fader_lag)).clamp(0.0, 1.0);
+        self.fader_curve.gain(x)
+    }
+
+    pub fn get_fader_curve(&self) -> (FaderCurve, f32) {
+        (self.fader_curve, self.fader_lag)
+    }
+
+    /// Set the fader curve and cut-in lag, the lag is limited to 0.0..=0.5
+    pub fn set_fader_curve(&mut self, curve: FaderCurve, lag: f32) {
+        let lag = lag.clamp(0.0, 0.5);
+        if (curve, lag) != (self.fader_curve, self.fader_lag) {
+            self.fader_curve = curve;
+            self.fader_lag = lag;
+            self.changed = true;
+            self.revision += 1;
+        }
+    }
+
+    // Value of the cut spline in bars, None outside the knots
+    pub fn spline_value(&self, bar_pos: f32) -> Option<f32> {
+        self.custom_value(bar_pos)
+            .or_else(|| self.cut_spline.sample(bar_pos))
+    }
+
+    /// Value of segments not evaluated by the spline: cubic Bezier next to
+    /// tangent handles, monotone cubic Hermite in Catmull-Rom segments when
+    /// monotone. None otherwise.
+    fn custom_value(&self, bar_pos: f32) -> Option<f32> {
+        // the keys are the knots, with the end values of a loop
+        let keys = self.cut_spline.keys();
+        let i = keys.partition_point(|k| k.t <= bar_pos).checked_sub(1)?;
+        let (k0, k1) = (keys.get(i)?, keys.get(i + 1)?);
+        let h = k1.t - k0.t;
+        let s = (bar_pos - k0.t) / h;
+        let (s2, s3) = (s * s, s * s * s);
+
+        let right = self.cut_knots.get(i)?.handles.map(|h| h.right);
+        let left = self.cut_knots.get(i + 1)?.handles.map(|h| h.left);
+        if right.is_some() || left.is_some() {
+            let c0 = k0.value + right.unwrap_or(0.0);
+            let c1 = k1.value + left.unwrap_or(0.0);
+            let r = 1.0 - s;
+            return Some(
+                r * r * r * k0.value + 3.0 * r * r * s * c0 + 3.0 * r * s2 * c1 + s3 * k1.value,
+            );
+        }
+
+        if !self.monotone || !matches!(k0.interpolation, splines::Interpolation::CatmullRom) {
+            return None;
+        }
+        let (m0, m1) = (self.cut_tangents[i], self.cut_tangents[i + 1]);
+        Some(
+            (2.0 * s3 - 3.0 * s2 + 1.0) * k0.value
+                + (s3 - 2.0 * s2 + s) * h * m0
+                + (-2.0 * s3 + 3.0 * s2) * k1.value
+                + (s3 - s2) * h * m1,
+        )
+    }
+
+    /// Positions (in bars) of the left and right handle of cut knot index
+    pub fn handle_positions(&self, index: usize) -> Option<(Pos2, Pos2)> {
+        let handles = self.cut_knots.get(index)?.handles?;
+        let keys = self.cut_spline.keys();
+        let prev = keys.get(index.checked_sub(1)?)?;
+        let (knot, next) = (keys.get(index)?, keys.get(index + 1)?);
+        Some((
+            pos2(knot.t - (knot.t - prev.t) / 3.0, knot.value + handles.left),
+            pos2(knot.t + (next.t - knot.t) / 3.0, knot.value + handles.right),
+        ))
+    }
+
+    // Value of the fader spline in bars, None outside the knots
+    pub fn fader_value(&self, bar_pos: f32) -> Option<f32> {
+        self.fader_spline.sample(bar_pos)
+    }
+
+    /// Map a spline value into the sample range 0.0..=1.0.
+    /// Values outside the range wrap around when warping, else they are clamped.
+    pub fn wrap_value(&self, y: f32) -> f32 {
+        if self.warping && !(0.0..=1.0).contains(&y) {
+            y.rem_euclid(1.0)
+        } else {
+            y.clamp(0.0, 1.0)
+        }
+    }
+
+    pub fn is_warping(&self) -> bool {
+        self.warping
+    }
+
+    pub fn set_warping(&mut self, warping: bool) {
+        self.warping = warping;
+        self.changed = true;
+        self.revision += 1;
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn is_monotone(&self) -> bool {
+        self.monotone
+    }
+
+    pub fn set_monotone(&mut self, monotone: bool) {
+        self.monotone = monotone;
+        self.changed = true;
+        self.cut_spline_update();
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+        self.changed = true;
+        self.cut_spline_update();
+        self.fader_spline_update();
+    }
+
+    pub fn get_bars(&self) -> f32 {
+        self.bars
+    }
+
+    /// Change the length, the guard knots stay 1/4 bar outside the cut
+    pub fn set_bars(&mut self, bars: f32, resize: Resize) -> Vec<CheckPointData> {
+        if !(bars > 0.0 && bars.is_finite()) || bars == self.bars {
+            return vec![];
+        }
+        debug!("set_bars {} -> {} {:?}", self.bars, bars, resize);
+        let checkpoint = vec![
+            CheckPointData::Bars(self.bars),
+            CheckPointData::CutKnots(self.cut_knots.clone()),
+            CheckPointData::FaderKnots(self.fader_knots.clone()),
+        ];
+
+        let len = self.cut_knots.len();
+        let (mut cut_knots, mut fader_knots) = match resize {
+            Resize::Stretch => {
+                let ratio = bars / self.bars;
+                let scale = |knots: &[Knot]| -> Vec<Knot> {
+                    knots
+                        .iter()
+                        .map(|k| Knot {
+                            pos: pos2(k.pos.x * ratio, k.pos.y),
+                            ..*k
+                        })
+                        .collect()
+                };
+                (scale(&self.cut_knots[..len - 1]), scale(&self.fader_knots))
+            }
+            Resize::ExtendTrim if bars > self.bars => {
+                // hold the end values over the added bars
+                let mut cut_knots = self.cut_knots[..len - 1].to_vec();
+                let end = cut_knots[len - 2];
+                cut_knots.push(Knot::new(pos2(bars, end.pos.y)));
+
+                let mut fader_knots = self.fader_knots.clone();
+                let end = fader_knots[fader_knots.len() - 1];
+                fader_knots.push(Knot::new(pos2(bars, end.pos.y)));
+                (cut_knots, fader_knots)
+            }
+            Resize::ExtendTrim => {
+                // cut off the knots at and beyond the new end
+                let y = self.spline_value(bars).unwrap_or(0.0).clamp(0.0, 1.0);
+                let mut cut_knots: Vec<Knot> = self.cut_knots[..len - 1]
+                    .iter()
+                    .filter(|k| k.pos.x < bars)
+                    .copied()
+                    .collect();
+                cut_knots.push(Knot::new(pos2(bars, y)));
+
+                let y = self.fader_value(bars).unwrap_or(0.0);
+                let mut fader_knots: Vec<Knot> = self
+                    .fader_knots
+                    .iter()
+                    .filter(|k| k.pos.x < bars)
+                    .copied()
+                    .collect();
+                fader_knots.push(Knot::new(pos2(bars, y)));
+                (cut_knots, fader_knots)
+            }
+        };
+        // guard knots
+        cut_knots[0].pos.x = -0.25;
+        cut_knots.push(Knot::new(pos2(bars + 0.25, 0.0)));
+        if let Some(first) = fader_knots.first_mut() {
+            first.pos.x = 0.0;
+        }
+
+        self.bars = bars;
+        self.set_cut_knots(cut_knots);
+        self.set_fader_knots(fader_knots);
+        checkpoint
+    }
+
+    /// Replace the knots from the first to the last x of cut_knots by cut_knots
+    /// and fader_knots, which must span the same range within the cut. The
+    /// guard knots and the end points keep their x, their y is set.
+    pub fn insert_knots(
+        &mut self,
+        cut_knots: Vec<Knot>,
+        fader_knots: Vec<Knot>,
+    ) -> Vec<CheckPointData> {
+        let (Some(first), Some(last)) = (cut_knots.first(), cut_knots.last()) else {
+            return vec![];
+        };
+        let (start, end) = (first.pos.x, last.pos.x);
+        debug!("insert_knots {}..{}", start, end);
+        let checkpoint = vec![
+            CheckPointData::CutKnots(self.cut_knots.clone()),
+            CheckPointData::FaderKnots(self.fader_knots.clone()),
+        ];
+
+        // cut knots, keep first 2 and last 2
+        let len = self.cut_knots.len();
+        let mut knots: Vec<Knot> = self
+            .cut_knots
+            .iter()
+            .enumerate()
+            .filter(|(i, k)| *i < 2 || *i >= len - 2 || k.pos.x < start || k.pos.x > end)
+            .map(|(_, k)| Knot {
+                selected: false,
+                ..*k
+            })
+            .collect();
+        let last = knots.len() - 2;
+        for knot in cut_knots {
+            if knot.pos.x <= 0.0 {
+                knots[1].pos.y = knot.pos.y;
+            } else if knot.pos.x >= self.bars {
+                knots[last].pos.y = knot.pos.y;
+            } else {
+                knots.push(knot);
+            }
+        }
+        knots.sort_by(|a, b| a.pos.x.total_cmp(&b.pos.x));
+
+        // fader knots, in order, may share x, cut back to the old fader at both ends
+        let at = |x: f32| self.fader_knots.iter().filter(move |k| k.pos.x == x);
+        let before = at(start).next().map(|k| k.pos.y);
+        let after = at(end).next_back().map(|k| k.pos.y);
+        let before = before.or_else(|| self.fader_value(start));
+        let after = after.or_else(|| self.fader_value(end));
+        let mut fader_knots = fader_knots;
+        if let Some(y) = before.filter(|_| start > 0.0) {
+            fader_knots.insert(0, Knot::new(pos2(start, y)));
+        }
+        if let Some(y) = after.filter(|_| end < self.bars) {
+            fader_knots.push(Knot::new(pos2(end, y)));
+        }
+        let outside = |after: bool| {
+            self.fader_knots
+                .iter()
+                .filter(move |k| {
+                    if after {
+                        k.pos.x > end
+                    } else {
+                        k.pos.x < start
+                    }
+                })
+                .map(|k| Knot {
+                    selected: false,
+                    ..*k
+                })
+        };
+        let mut faders: Vec<Knot> = outside(false)
+            .chain(fader_knots)
+            .chain(outside(true))
+            .collect();
+        faders.dedup_by(|a, b| a.pos == b.pos);
+
+        self.set_cut_knots(knots);
+        self.set_fader_knots(faders);
+        self.changed = true;
+        checkpoint
+    }
+
+    pub fn get_quantization(&self) -> u32 {
+        self.quantization
+    }
+
+    /// Change the snapping grid, the knots keep their positions
+    pub fn set_quantization(&mut self, quantization: u32) {
+        if quantization > 0 && quantization != self.quantization {
+            self.quantization = quantization;
+            self.changed = true;
+        }
+    }
+
+    pub fn get_swing(&self) -> f32 {
+        self.swing
+    }
+
+    /// Change the swing of the grid, the knots keep their positions
+    pub fn set_swing(&mut self, swing: f32) {
+        if swing != self.swing {
+            self.swing = swing;
+            self.changed = true;
+        }
+    }
+
+    /// Snapping grid, from quantization and swing
+    pub fn grid(&self) -> Grid {
+        Grid::new(self.quantization, self.swing)
+    }
+
+    /// Round x (in bars) to the quantization grid
+    pub fn quantize(&self, x: f32) -> f32 {
+        self.grid().snap(x)
+    }
+
+    pub fn cut_knots(&self) -> &[Knot] {
+        &self.cut_knots
+    }
+
+    pub fn fader_knots(&self) -> &[Knot] {
+        &self.fader_knots
+    }
+
+    // needs save if undo len > 0
+    pub fn needs_save(&self) -> bool {
+        !self.undo.is_empty() || self.wav.needs_save() || self.changed
+    }
+
+    // mark as changed, e.g., on render settings edits
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+        self.revision += 1;
+    }
+
+    /// Changes whenever the rendered audio may have changed
+    pub fn revision(&self) -> u64 {
+        self.revision + self.wav.revision()
+    }
+
+    /// Copy of the cut for rendering, without undo history. The sample data
+    /// is shared, not copied.
+    pub fn snapshot(&self) -> Cut {
+        Cut {
+            version: self.version,
+            cut_path: self.cut_path.clone(),
+            sample_path: self.sample_path.clone(),
+            sample_size: self.sample_size,
+            sample_hash: self.sample_hash,
+            quantization: self.quantization,
+            swing: self.swing,
+            bars: self.bars,
+            cut_knots: self.cut_knots.clone(),
+            fader_knots: self.fader_knots.clone(),
+            looping: self.looping,
+            warping: self.warping,
+            monotone: self.monotone,
+            fader_curve: self.fader_curve,
+            fader_lag: self.fader_lag,
+            wav: self.wav.clone(),
+            render: self.render.clone(),
+            changed: self.changed,
+            undo: vec![],
+            redo: vec![],
+            cut_spline: self.cut_spline.clone(),
+            fader_spline: self.fader_spline.clone(),
+            cut_tangents: self.cut_tangents.clone(),
+            wav_data: self.wav_data.clone(),
+            relink_candidates: vec![],
+            project_rate: self.project_rate,
+            revision: self.revision,
+        }
+    }
+
+    // name
+    pub fn name(&self) -> String {
+        self.cut_path
+            .file_name()
+            .map_or("<TBD>".to_string(), |path| {
+                path.to_string_lossy().to_string()
+            })
+    }
+
+    // get name
+    pub fn get_name(&self) -> String {
+        format!(
+            "{}{}",
+            self.name(),
+            if self.needs_save() { "*" } else { "" }
+        )
+    }
+
+    // get undo len
+    pub fn get_undo_len(&self) -> usize {
+        self.undo.len() + self.wav.get_undo_len()
+    }
+
+    // get redo len
+    pub fn get_redo_len(&self) -> usize {
+        self.redo.len() + self.wav.get_redo_len()
+    }
+
+    // load cut or wav from path, the sample is converted to project_rate if given
+    pub fn load_path(
+        mut path: PathBuf,
+        project_rate: Option<u32>,
+    ) -> Result<(Cut, String), CutError> {
+        trace!("path {:?}", path);
+
+        let ext = path
+            .extension()
+            .map_or("".to_string(), |ext| ext.to_string_lossy().to_string());
+        trace!("ext {:?}", ext);
+        match ext.as_str() {
+            sample if SAMPLE_EXTENSIONS.contains(&sample.to_lowercase().as_str()) => {
+                // loading sample only, set the cut to default
+                debug!("load sample (only)");
+                let mut cut = Cut {
+                    project_rate,
+                    ..Cut::default()
+                };
+                cut.load_sample(path.clone())?;
+
+                debug!("path {}", path.display());
+                path.set_extension("cut");
+                cut.cut_path = path;
+                Ok((cut, sample.to_string()))
+            }
+            "cut" => {
+                debug!("load cut");
+                let json = std::fs::read_to_string(&path)?;
+                trace!("json {}", json);
+                let mut cut = cut_file::from_json(&json)?;
+                trace!("cut {:?}", cut);
+                cut.project_rate = project_rate;
+
+                // the cut may have been moved since saved
+                let cut_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                cut.cut_path = path;
+                cut.cut_spline_update();
+                cut.fader_spline_update();
+
+                let Some(sample_path) = cut.sample_path.clone() else {
+                    return Ok((cut, "cut".to_string()));
+                };
+
+                let sample_path = cut_file::resolve_path(&cut_dir, &sample_path);
+                let unreadable = if sample_path.exists() {
+                    match cut.load_sample(sample_path.clone()) {
+                        Ok(()) => return Ok((cut, "cut".to_string())),
+                        Err(err) => Some(err),
+                    }
+                } else {
+                    None
+                };
+
+                // sample moved or unreadable, look for it nearby
+                debug!("sample missing {:?}, {:?}", sample_path, unreadable);
+                cut.sample_path = Some(sample_path.clone());
+                let info = cut.sample_size.zip(cut.sample_hash);
+                let (mut candidates, mut sure) =
+                    cut_file::relink_candidates(&cut_dir, &sample_path, info);
+                if unreadable.is_some() {
+                    let same = |path: &Path| {
+                        std::fs::canonicalize(path).ok() == std::fs::canonicalize(&sample_path).ok()
+                    };
+                    sure &= !candidates.first().is_some_and(|first| same(first));
+                    candidates.retain(|candidate| !same(candidate));
+                }
+                if sure {
+                    let relinked = candidates[0].clone();
+                    match cut.relink_sample(relinked.clone()) {
+                        Ok(()) => {
+                            return Ok((cut, format!("cut, sample relinked to {:?}", relinked)))
+                        }
+                        Err(err) => debug!("relink {:?} failed, {}", relinked, err),
+                    }
+                }
+                cut.relink_candidates = candidates;
+                let msg = match unreadable {
+                    Some(err) => format!("cut, {}", err),
+                    None => format!("cut, sample missing {:?}", sample_path),
+                };
+                Ok((cut, msg))
+            }
+            _ => Err(CutError::UnsupportedType(ext)),
+        }
+    }
+
+    /// load sample, the window is kept if it fits the sample
+    fn load_sample(&mut self, path: PathBuf) -> Result<(), CutError> {
+        let mut wav_data = WavData::load_wav_data(path.clone()).map_err(CutError::Sample)?;
+        // windows without recorded rate are in samples of the file
+        self.wav.set_rate(wav_data.get_rate());
+        if let Some(rate) = self.project_rate {
+            wav_data.convert_rate(rate).map_err(CutError::Sample)?;
+            self.wav.set_rate(rate);
+        }
+        self.wav_data = wav_data;
+        self.revision += 1;
+
+        let len = self.wav_data.len;
+        if self.wav.get_data_len() == 0 || self.wav.get_data_len() > len {
+            self.wav.set_data_len(len);
+        }
+        if self.wav.get_data_offset() >= len {
+            self.wav.set_data_offset(0);
+        }
+        if let Some((size, hash)) = cut_file::file_info(&path) {
+            self.sample_size = Some(size);
+            self.sample_hash = Some(hash);
+        }
+        self.sample_path = Some(path);
+        self.relink_candidates = vec![];
+        Ok(())
+    }
+
+    /// Set the project sample rate, samples are converted to it on load.
+    /// A loaded sample is reloaded, converting from the file rate.
+    pub fn set_project_rate(&mut self, rate: u32) -> Result<(), CutError> {
+        self.project_rate = Some(rate);
+        if self.wav_data.len > 0 && self.wav_data.get_rate() != rate {
+            if let Some(path) = self.sample_path.clone() {
+                self.load_sample(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace a missing (or any) sample
+    pub fn relink_sample(&mut self, path: PathBuf) -> Result<(), CutError> {
+        debug!("relink sample {:?}", path);
+        self.load_sample(path)?;
+        self.changed = true;
+        Ok(())
+    }
+
+    /// The cut refers to a sample that is not loaded
+    pub fn sample_missing(&self) -> bool {
+        self.sample_path.is_some() && self.wav_data.len == 0
+    }
+
+    /// Check the invariants the editor and renderer rely on
+    pub fn validate(&self) -> Result<(), CutError> {
+        if !(self.bars > 0.0 && self.bars.is_finite()) {
+            Err(CutError::InvalidBars(self.bars))?;
+        }
+        if self.quantization == 0 {
+            Err(CutError::InvalidQuantization(self.quantization))?;
+        }
+        self.render.validate().map_err(CutError::InvalidRender)?;
+        if let FaderCurve::Exponent(exponent) = self.fader_curve {
+            if !(exponent > 0.0 && exponent.is_finite()) {
+                Err(CutError::InvalidFaderCurve(exponent))?;
+            }
+        }
+        if !(0.0..=0.5).contains(&self.fader_lag) {
+            Err(CutError::InvalidFaderLag(self.fader_lag))?;
+        }
+
+        // two guard knots and the start and end points
+        if self.cut_knots.len() < 4 {
+            Err(CutError::TooFewKnots {
+                lane: "cut",
+                len: self.cut_knots.len(),
+                min: 4,
+            })?;
+        }
+        if self.fader_knots.len() < 2 {
+            Err(CutError::TooFewKnots {
+                lane: "fader",
+                len: self.fader_knots.len(),
+                min: 2,
+            })?;
+        }
+
+        // cut knots are strictly increasing, fader knots may share x for hard cuts
+        if let Some(index) = (1..self.cut_knots.len())
+            .find(|&i| self.cut_knots[i].pos.x <= self.cut_knots[i - 1].pos.x)
+        {
+            Err(CutError::NonMonotonic { lane: "cut", index })?;
+        }
+        if let Some(index) = (1..self.fader_knots.len())
+            .find(|&i| self.fader_knots[i].pos.x < self.fader_knots[i - 1].pos.x)
+        {
+            Err(CutError::NonMonotonic {
+                lane: "fader",
+                index,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// save cut to cut_path
+    pub fn save(&mut self) -> Result<(), String> {
+        self.version = cut_file::VERSION;
+        // Serialize it to a JSON string.
+        let json = cut_file::to_json(self).map_err(|err| format!("{:?}", err))?;
+        debug!("save_cut {}", json);
+
+        let mut file = File::create(&self.cut_path).map_err(|err| format!("{:?}", err))?;
+        self.redo = vec![];
+        self.undo = vec![];
+        self.wav.clear_undo_redo();
+        self.changed = false;
+        file.write_all(json.as_bytes()).map_err(|err| {
+            debug!("Err {:?}", err);
+            format!("{:?}", err)
+        })
+    }
+
+    /// call to update cut spline when knots are changed
+    pub fn cut_spline_update(&mut self) {
+        // add a knot to the spline
+        #[inline(always)]
+        fn key(knot: &Knot) -> splines::Key<f32, f32> {
+            let interpolation = knot.interpolation.key(knot.pos.y);
+            splines::Key::new(knot.pos.x, knot.pos.y, interpolation)
+        }
+        trace!("update knots and spline");
+        self.revision += 1;
+        let len = self.cut_knots.len();
+        // ensure that endpoints are aligned
+        self.cut_knots[0].pos.y = self.cut_knots[1].pos.y;
+        self.cut_knots[len - 1].pos.y = self.cut_knots[len - 2].pos.y;
+
+        // add knots besides last two
+        self.cut_spline = Spline::from_iter(self.cut_knots[..len - 2].iter().map(key));
+
+        // add last two knots
+        if self.looping {
+            let y = self.cut_knots[0].pos.y;
+            for knot in &self.cut_knots[len - 2..] {
+                self.cut_spline.add(key(&Knot {
+                    pos: pos2(knot.pos.x, y),
+                    ..*knot
+                }));
+            }
+        } else {
+            self.cut_spline.add(key(&self.cut_knots[len - 2]));
+            self.cut_spline.add(key(&self.cut_knots[len - 1]));
+        }
+        self.cut_tangents = monotone_tangents(self.cut_spline.keys());
+    }
+
+    /// call to update spline when knots are changed
+    pub fn fader_spline_update(&mut self) {
+        self.revision += 1;
+        let len = self.fader_knots.len();
+
+        self.fader_spline = Spline::from_iter(
+            self.fader_knots[..len - 1]
+                .iter()
+                .map(|k| splines::Key::new(k.pos.x, k.pos.y, splines::Interpolation::Linear)),
+        );
+
+        // add last knot
+        if self.looping {
+            self.fader_spline.add(splines::Key::new(
+                self.fader_knots[len - 1].pos.x,
+                0.0,
+                splines::Interpolation::Linear,
+            ));
+        } else {
+            let pos = self.fader_knots[len - 1].pos;
+            self.fader_spline.add(splines::Key::new(
+                pos.x,
+                pos.y,
+                splines::Interpolation::Linear,
+            ));
+        }
+        debug!("spline update {:?}", self.fader_spline);
+    }
+
+    /// Replace the cut knots
+    pub fn set_cut_knots(&mut self, cut_knots: Vec<Knot>) {
+        self.cut_knots = cut_knots;
+        self.cut_spline_update();
+    }
+
+    /// Replace the fader knots
+    pub fn set_fader_knots(&mut self, fader_knots: Vec<Knot>) {
+        self.fader_knots = fader_knots;
+        self.fader_spline_update();
+    }
+
+    /// Delete selected knots, guard knots and end points are kept
+    pub fn delete_selected(&mut self) -> Vec<CheckPointData> {
+        let mut checkpoint = vec![];
+
+        // cut knots, keep first 2 and last 2
+        let len = self.cut_knots.len();
+        let cut_knots: Vec<Knot> = self
+            .cut_knots
+            .iter()
+            .enumerate()
+            .filter(|(index, k)| !(k.selected && *index > 1 && *index < len - 2))
+            .map(|(_, k)| *k)
+            .collect();
+
+        if cut_knots.len() != len {
+            debug!("delete cut knots");
+            checkpoint.push(CheckPointData::CutKnots(self.cut_knots.clone()));
+            self.set_cut_knots(cut_knots);
+        }
+
+        // fader knots, keep first and last
+        let len = self.fader_knots.len();
+        let fader_knots: Vec<Knot> = self
+            .fader_knots
+            .iter()
+            .enumerate()
+            .filter(|(index, k)| !(k.selected && *index > 0 && *index < len - 1))
+            .map(|(_, k)| *k)
+            .collect();
+
+        if fader_knots.len() != len {
+            debug!("delete fader knots");
+            checkpoint.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
+            self.set_fader_knots(fader_knots);
+        }
+
+        checkpoint
+    }
+
+    /// Unselect all knots
+    pub fn clear_selection(&mut self) -> Vec<CheckPointData> {
+        let mut checkpoint = vec![];
+        if self.cut_knots.iter().any(|cut_knot| cut_knot.selected) {
+            checkpoint.push(CheckPointData::CutKnots(self.cut_knots.clone()));
+        }
+        if self
+            .fader_knots
+            .iter()
+            .any(|fader_knot| fader_knot.selected)
+        {
+            checkpoint.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
+        }
+
+        self.cut_knots.iter_mut().for_each(|k| k.selected = false);
+        self.fader_knots.iter_mut().for_each(|k| k.selected = false);
+        checkpoint
+    }
+
+    /// Toggle selection of all cut knots for which `cut_contains` (position in
+    /// bars) holds, and of all fader knots for which `fader_contains` holds
+    pub fn toggle_select(
+        &mut self,
+        cut_contains: impl Fn(Pos2) -> bool,
+        fader_contains: impl Fn(Pos2) -> bool,
+    ) -> Vec<CheckPointData> {
+        let mut checkpoint = vec![];
+
+        // cut knots
+        if self.cut_knots.iter().any(|k| cut_contains(k.pos)) {
+            checkpoint.push(CheckPointData::CutKnots(self.cut_knots.clone()));
+        }
+        self.cut_knots
+            .iter_mut()
+            .filter(|k| cut_contains(k.pos))
+            .for_each(|k| k.selected ^= true);
+
+        // fader knots
+        if self.fader_knots.iter().any(|k| fader_contains(k.pos)) {
+            checkpoint.push(CheckPointData::FaderKnots(self.fader_knots.clone()));
+        }
+        self.fader_knots
+            .iter_mut()
+            .filter(|k| fader_contains(k.pos))
+            .for_each(|k| k.selected ^= true);
+
+        checkpoint
+    }
+
+    /// Set the interpolation of the selected cut knots and of the knot at index
+    pub fn set_interpolation(
+        &mut self,
+        index: usize,
+        interpolation: Interpolation,
+    ) -> Vec<CheckPointData> {
+        let checkpoint = vec![CheckPointData::CutKnots(self.cut_knots.clone())];
+        self.cut_knots
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, k)| k.selected || *i == index)
+            .for_each(|(_, k)| k.interpolation = interpolation);
+        self.cut_spline_update();
+        checkpoint
+    }
+
+    /// Add (flat) or remove tangent handles of the selected cut knots and of the knot at index
+    pub fn set_handles(&mut self, index: usize, handles: bool) -> Vec<CheckPointData> {
+        let checkpoint = vec![CheckPointData::CutKnots(self.cut_knots.clone())];
+        self.cut_knots
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, k)| k.selected || *i == index)
+            .for_each(|(_, k)| k.handles = handles.then_some(k.handles.unwrap_or_default()));
+        self.cut_spline_update();
+        checkpoint
+    }
+
+    /// Move the left or right handle of cut knot index to y (in bars)
+    pub fn move_handle(&mut self, index: usize, right: bool, y: f32) {
+        let knot = &mut self.cut_knots[index];
+        if let Some(handles) = &mut knot.handles {
+            let offset = y - knot.pos.y;
+            if right {
+                handles.right = offset;
+            } else {
+                handles.left = offset;
+            }
+            self.cut_spline_update();
+        }
+    }
+
+    /// Toggle selection of a single cut knot
+    pub fn toggle_cut_knot(&mut self, index: usize) {
+        self.cut_knots[index].selected ^= true;
+    }
+
+    /// Toggle selection of a single fader knot
+    pub fn toggle_fader_knot(&mut self, index: usize) {
+        self.fader_knots[index].selected ^= true;
+    }
+
+    /// Move selected knots relative to their initial positions.
+    ///
+    /// `delta_x` is the direction of the last move, knots are updated
+    /// front to back so that they can not pass each other.
+    pub fn move_selected(
+        &mut self,
+        cut_initial: &[Knot],
+        fader_initial: &[Knot],
+        bar_rel: Vec2,
+        delta_x: f32,
+    ) {
+        let cut_len = self.cut_knots.len().min(cut_initial.len());
+        let fader_len = self.fader_knots.len().min(fader_initial.len());
+
+        if delta_x > 0.0 {
+            trace!("right");
+
+            // cut knots
+            // right. we have to update rightmost knot first
+            // exclude first 2 and last 2 knots, they have fixed x positions
+            for i in (2..cut_len - 2).rev() {
+                if self.cut_knots[i].selected {
+                    let knot_pos_x = self.quantize(cut_initial[i].pos.x + bar_rel.x);
+
+                    if knot_pos_x < self.cut_knots[i + 1].pos.x
+                        && knot_pos_x > self.cut_knots[i - 1].pos.x
+                    {
+                        self.cut_knots[i].pos.x = knot_pos_x;
+                    }
+                }
+            }
+
+            // fader knots
+            // right. we have to update rightmost knot first
+            // exclude first and last knots, they have fixed x positions
+            for i in (1..fader_len - 1).rev() {
+                if self.fader_knots[i].selected {
+                    let knot_pos_x = self.quantize(fader_initial[i].pos.x + bar_rel.x);
+
+                    if knot_pos_x <= self.fader_knots[i + 1].pos.x
+                        && knot_pos_x >= self.fader_knots[i - 1].pos.x
+                    {
+                        self.fader_knots[i].pos.x = knot_pos_x;
+                    }
+                }
+            }
+        } else if delta_x < 0.0 {
+            trace!("left");
+
+            // cut knots
+            // left we update leftmost knot first
+            // we exclude first 2 and last 2 knots, they have fixed positions
+            for (i, initial) in cut_initial.iter().enumerate().take(cut_len - 2).skip(2) {
+                if self.cut_knots[i].selected {
+                    let knot_pos_x = self.quantize(initial.pos.x + bar_rel.x);
+
+                    if knot_pos_x > self.cut_knots[i - 1].pos.x
+                        && knot_pos_x < self.cut_knots[i + 1].pos.x
+                    {
+                        self.cut_knots[i].pos.x = knot_pos_x;
+                    }
+                }
+            }
+
+            // fader knots
+            // left we update leftmost knot first
+            // we exclude first and last knots, they have fixed positions
+            for (i, initial) in fader_initial.iter().enumerate().take(fader_len - 1).skip(1) {
+                if self.fader_knots[i].selected {
+                    let knot_pos_x = self.quantize(initial.pos.x + bar_rel.x);
+
+                    if knot_pos_x >= self.fader_knots[i - 1].pos.x
+                        && knot_pos_x <= self.fader_knots[i + 1].pos.x
+                    {
+                        self.fader_knots[i].pos.x = knot_pos_x;
+                    }
+                }
+            }
+        }
+
+        // cut knots up/down
+        for (knot, initial) in self.cut_knots[1..cut_len - 1]
+            .iter_mut()
+            .zip(&cut_initial[1..cut_len - 1])
+        {
+            if knot.selected {
+                knot.pos.y = (initial.pos.y + bar_rel.y).clamp(0.0, 1.0);
+            }
+        }
+
+        self.cut_spline_update();
+        self.fader_spline_update();
+    }
+
+    /// Move a single cut knot to pos (in bars), x is quantized
+    pub fn move_cut_knot(&mut self, index: usize, pos: Pos2) {
+        let knot_pos_x = self.quantize(pos.x);
+        trace!("rounded {:?}", knot_pos_x);
+
+        // never move first 2 and last 2 knots in x direction
+        let len = self.cut_knots.len();
+        if index > 1 && index < len - 2 {
+            let x = self.cut_knots[index].pos.x;
+            if knot_pos_x > x {
+                // right
+                if knot_pos_x < self.cut_knots[index + 1].pos.x {
+                    self.cut_knots[index].pos.x = knot_pos_x;
+                }
+            } else if knot_pos_x < x {
+                // left
+                if knot_pos_x > self.cut_knots[index - 1].pos.x {
+                    self.cut_knots[index].pos.x = knot_pos_x;
+                }
+            }
+        }
+
+        self.cut_knots[index].pos.y = pos.y.clamp(0.0, 1.0); // clamp to range
+        self.cut_spline_update();
+    }
+
+    /// Move a single fader knot to pos (in bars), x is quantized
+    pub fn move_fader_knot(&mut self, index: usize, pos: Pos2) {
+        let knot_pos_x = self.quantize(pos.x);
+        trace!("rounded {:?}", knot_pos_x);
+
+        // never move first and last knot
+        // we could think about clamping to 0.0, 1.0 for binary fader
+        let len = self.fader_knots.len();
+        if index > 0 && index < len - 1 {
+            let x = self.fader_knots[index].pos.x;
+            if knot_pos_x >= x {
+                // right
+                trace!("-- right");
+                if knot_pos_x <= self.fader_knots[index + 1].pos.x {
+                    self.fader_knots[index].pos.x = knot_pos_x;
+                }
+            } else if knot_pos_x < x {
+                // left
+                trace!("-- left");
+                if knot_pos_x >= self.fader_knots[index - 1].pos.x {
+                    self.fader_knots[index].pos.x = knot_pos_x;
+                }
+            }
+        }
+
+        self.fader_knots[index].pos.y = pos.y.clamp(0.0, 1.0); // clamp to range
+        self.fader_spline_update();
+    }
+
+    /// Insert a cut knot at pos (in bars), or move the knot at the same quantized x
+    pub fn insert_cut_knot(&mut self, mut pos: Pos2) -> CheckPointData {
+        pos.x = self.quantize(pos.x);
+
+        let checkpoint = CheckPointData::CutKnots(self.cut_knots.clone());
+
+        // insert, or move
+        if let Some(k) = self.cut_knots.iter_mut().find(|k| k.pos.x == pos.x) {
+            k.pos.y = pos.y;
+        } else {
+            trace!("new cut knot point");
+            let index = self.cut_knots.partition_point(|k| k.pos.x <= pos.x);
+            self.cut_knots.insert(index, Knot::new(pos));
+
+            trace!("cut knots {:?}", self.cut_knots);
+        }
+        self.cut_spline_update();
+        checkpoint
+    }
+
+    /// Insert a fader knot at pos (in bars), x is quantized
+    pub fn insert_fader_knot(&mut self, mut pos: Pos2) -> CheckPointData {
+        pos.x = self.quantize(pos.x);
+
+        let checkpoint = CheckPointData::FaderKnots(self.fader_knots.clone());
+
+        debug!("new fader knot point");
+        let index = self.fader_knots.partition_point(|k| k.pos.x <= pos.x);
+        self.fader_knots.insert(index, Knot::new(pos));
+
+        debug!("fader knots {:?}", self.fader_knots);
+        self.fader_spline_update();
+        checkpoint
+    }
+
+    /// Store undo checkpoint, if non empty
+    pub fn push_checkpoint(&mut self, checkpoint: Vec<CheckPointData>) {
+        if !checkpoint.is_empty() {
+            debug!("store undo checkpoint");
+            self.undo.push(checkpoint);
+        }
+    }
+
+    /// Restore checkpoint data, returns the data it replaced
+    fn restore(&mut self, check_point_data: CheckPointData) -> CheckPointData {
+        match check_point_data {
+            CheckPointData::CutKnots(cut_knots) => {
+                debug!("restore cut_knots {:?}", cut_knots);
+                let old = CheckPointData::CutKnots(self.cut_knots.clone());
+                self.set_cut_knots(cut_knots);
+                old
+            }
+            CheckPointData::FaderKnots(fader_knots) => {
+                debug!("restore fader_knots {:?}", fader_knots);
+                let old = CheckPointData::FaderKnots(self.fader_knots.clone());
+                self.set_fader_knots(fader_knots);
+                old
+            }
+            CheckPointData::CutKnot(IndexKnot { index, knot }) => {
+                debug!("restore cut_knot {:?}", index);
+                let old = CheckPointData::CutKnot(IndexKnot {
+                    index,
+                    knot: self.cut_knots[index],
+                });
+                self.cut_knots[index] = knot;
+                self.cut_spline_update();
+                old
+            }
+            CheckPointData::FaderKnot(IndexKnot { index, knot }) => {
+                debug!("restore fader knot {:?}", index);
+                let old = CheckPointData::FaderKnot(IndexKnot {
+                    index,
+                    knot: self.fader_knots[index],
+                });
+                self.fader_knots[index] = knot;
+                self.fader_spline_update();
+                old
+            }
+            CheckPointData::Bars(bars) => {
+                debug!("restore bars {}", bars);
+                let old = CheckPointData::Bars(self.bars);
+                self.bars = bars;
+                self.revision += 1;
+                old
+            }
+        }
+    }
+
+    /// Undo last checkpoint
+    pub fn undo(&mut self) {
+        if let Some(check_point) = self.undo.pop() {
+            // restore in reverse order, in case the checkpoint touches the same knots twice
+            let redo: Vec<CheckPointData> = check_point
+                .into_iter()
+                .rev()
+                .map(|check_point_data| self.restore(check_point_data))
+                .collect();
+            if !redo.is_empty() {
+                debug!("store redo checkpoint");
+                self.redo.push(redo);
+            }
+        }
+    }
+
+    /// Redo last undone checkpoint
+    pub fn redo(&mut self) {
+        if let Some(check_point) = self.redo.pop() {
+            let undo: Vec<CheckPointData> = check_point
+                .into_iter()
+                .rev()
+                .map(|check_point_data| self.restore(check_point_data))
+                .collect();
+            if !undo.is_empty() {
+                debug!("store undo checkpoint");
+                self.undo.push(undo);
+            }
+        }
+    }
+}
+
+/// Tangents of a monotone cubic Hermite spline through the keys (Fritsch-Carlson)
+fn monotone_tangents(keys: &[splines::Key<f32, f32>]) -> Vec<f32> {
+    let n = keys.len();
+    if n < 2 {
+        return vec![0.0; n];
+    }
+    // secants
+    let d: Vec<f32> = keys
+        .windows(2)
+        .map(|k| (k[1].value - k[0].value) / (k[1].t - k[0].t))
+        .collect();
+
+    let mut m = vec![0.0; n];
+    m[0] = d[0];
+    m[n - 1] = d[n - 2];
+    for i in 1..n - 1 {
+        // flat at extrema
+        if d[i - 1] * d[i] > 0.0 {
+            m[i] = (d[i - 1] + d[i]) / 2.0;
+        }
+    }
+
+    // limit the tangents so the segments do not overshoot
+    for i in 0..n - 1 {
+        if d[i] == 0.0 {
+            m[i] = 0.0;
+            m[i + 1] = 0.0;
+            continue;
+        }
+        let a = m[i] / d[i];
+        let b = m[i + 1] / d[i];
+        let r = a * a + b * b;
+        if r > 9.0 {
+            let tau = 3.0 / r.sqrt();
+            m[i] = tau * a * d[i];
+            m[i + 1] = tau * b * d[i];
+        }
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::vec2;
+
+    fn positions(knots: &[Knot]) -> Vec<Pos2> {
+        knots.iter().map(|k| k.pos).collect()
+    }
+
+    fn xs(knots: &[Knot]) -> Vec<f32> {
+        knots.iter().map(|k| k.pos.x).collect()
+    }
+
+    fn knots(points: &[(f32, f32)]) -> Vec<Knot> {
+        points
+            .iter()
+            .map(|(x, y)| Knot::new(pos2(*x, *y)))
+            .collect()
+    }
+
+    fn selected(knots: &[Knot]) -> Vec<usize> {
+        (0..knots.len()).filter(|&i| knots[i].selected).collect()
+    }
+
+    /// Cut and fader knots, with their selection
+    fn lanes(cut: &Cut) -> (Vec<Knot>, Vec<Knot>) {
+        (cut.cut_knots().to_vec(), cut.fader_knots().to_vec())
+    }
+
+    /// Undo and redo the last checkpoint, the knots are back to before and after
+    fn undo_redo(cut: &mut Cut, before: &(Vec<Knot>, Vec<Knot>)) {
+        let after = lanes(cut);
+        cut.undo();
+        assert_eq!(&lanes(cut), before);
+        cut.redo();
+        assert_eq!(lanes(cut), after);
+    }
+
+    #[test]
+    fn toggle_select() {
+        let mut cut = Cut::default();
+        let before = lanes(&cut);
+        let in_first_bar = |pos: Pos2| (0.5..=1.0).contains(&pos.x);
+        let checkpoint = cut.toggle_select(in_first_bar, in_first_bar);
+        assert_eq!(checkpoint.len(), 2);
+        assert_eq!(selected(cut.cut_knots()), [2]);
+        assert_eq!(selected(cut.fader_knots()), [1, 2]);
+        cut.push_checkpoint(checkpoint);
+        undo_redo(&mut cut, &before);
+
+        // selected knots are unselected
+        let checkpoint = cut.toggle_select(|_| false, |pos| pos == pos2(1.0, 0.0));
+        assert_eq!(checkpoint.len(), 1);
+        assert_eq!(selected(cut.cut_knots()), [2]);
+        assert_eq!(selected(cut.fader_knots()), [2]);
+
+        // no knots, no checkpoint
+        assert!(cut
+            .toggle_select(|pos| pos.x > 10.0, |pos| pos.x > 10.0)
+            .is_empty());
+
+        cut.toggle_cut_knot(3);
+        cut.toggle_fader_knot(0);
+        assert_eq!(selected(cut.cut_knots()), [2, 3]);
+        assert_eq!(selected(cut.fader_knots()), [0, 2]);
+
+        // lanes by their own test
+        let mut cut = Cut::default();
+        assert_eq!(cut.toggle_select(|_| true, |_| false).len(), 1);
+        assert_eq!(selected(cut.cut_knots()), [0, 1, 2, 3, 4]);
+        assert!(selected(cut.fader_knots()).is_empty());
+    }
+
+    #[test]
+    fn clear_selection() {
+        let mut cut = Cut::default();
+        assert!(cut.clear_selection().is_empty());
+
+        cut.toggle_cut_knot(2);
+        let before = lanes(&cut);
+        let checkpoint = cut.clear_selection();
+        assert_eq!(checkpoint.len(), 1);
+        assert!(selected(cut.cut_knots()).is_empty());
+        cut.push_checkpoint(checkpoint);
+        undo_redo(&mut cut, &before);
+
+        cut.toggle_cut_knot(2);
+        cut.toggle_fader_knot(1);
+        assert_eq!(cut.clear_selection().len(), 2);
+        assert!(selected(cut.cut_knots()).is_empty());
+        assert!(selected(cut.fader_knots()).is_empty());
+    }
+
+    #[test]
+    fn delete_selected() {
+        let mut cut = Cut::default();
+        assert!(cut.delete_selected().is_empty());
+
+        // guard knots and end points are kept
+        cut.toggle_select(|_| true, |_| true);
+        let before = lanes(&cut);
+        let checkpoint = cut.delete_selected();
+        assert_eq!(checkpoint.len(), 2);
+        assert_eq!(
+            positions(cut.cut_knots()),
+            [
+                pos2(-0.25, 0.0),
+                pos2(0.0, 0.0),
+                pos2(2.0, 1.0),
+                pos2(2.25, 1.0)
+            ]
+        );
+        assert_eq!(
+            positions(cut.fader_knots()),
+            [pos2(0.0, 0.0), pos2(2.0, 0.0)]
+        );
+        assert!(cut.validate().is_ok());
+        cut.push_checkpoint(checkpoint);
+        undo_redo(&mut cut, &before);
+
+        // only the fader lane
+        let mut cut = Cut::default();
+        cut.toggle_fader_knot(3);
+        let checkpoint = cut.delete_selected();
+        assert!(matches!(checkpoint[..], [CheckPointData::FaderKnots(_)]));
+        assert_eq!(cut.fader_knots().len(), 5);
+        assert_eq!(cut.cut_knots().len(), 5);
+    }
+
+    #[test]
+    fn move_selected() {
+        let mut cut = Cut::default();
+        cut.toggle_cut_knot(2);
+        // the hard cut at 1.5
+        cut.toggle_fader_knot(3);
+        cut.toggle_fader_knot(4);
+        let before = lanes(&cut);
+        let (cut_initial, fader_initial) = lanes(&cut);
+
+        // right and up, quantized to the 1/16 grid
+        cut.move_selected(&cut_initial, &fader_initial, vec2(0.51, 0.25), 1.0);
+        assert_eq!(cut.cut_knots()[2].pos, pos2(1.5, 0.75));
+        assert_eq!(cut.fader_knots()[3].pos, pos2(2.0, 1.0));
+        assert_eq!(cut.fader_knots()[4].pos, pos2(2.0, 0.0));
+        // the end points do not move
+        assert_eq!(cut.cut_knots()[3].pos.x, 2.0);
+        assert_eq!(cut.fader_knots()[5].pos.x, 2.0);
+
+        cut.push_checkpoint(vec![
+            CheckPointData::CutKnots(cut_initial.clone()),
+            CheckPointData::FaderKnots(fader_initial.clone()),
+        ]);
+        undo_redo(&mut cut, &before);
+        cut.undo();
+
+        // past the next knot, the knots stay, y is clamped
+        cut.move_selected(&cut_initial, &fader_initial, vec2(1.5, 1.0), 1.0);
+        assert_eq!(cut.cut_knots()[2].pos, pos2(1.0, 1.0));
+        assert_eq!(cut.fader_knots()[3].pos.x, 1.5);
+        assert_eq!(cut.fader_knots()[4].pos.x, 1.5);
+
+        // left up to the previous fader knot, not past the previous cut knot
+        cut.move_selected(&cut_initial, &fader_initial, vec2(-0.5, -1.0), -1.0);
+        assert_eq!(cut.cut_knots()[2].pos, pos2(0.5, 0.0));
+        assert_eq!(cut.fader_knots()[3].pos.x, 1.0);
+        assert_eq!(cut.fader_knots()[4].pos.x, 1.0);
+        cut.move_selected(&cut_initial, &fader_initial, vec2(-1.5, 0.0), -1.0);
+        assert_eq!(cut.cut_knots()[2].pos.x, 0.5);
+        assert_eq!(cut.fader_knots()[3].pos.x, 1.0);
+
+        // the selected end point moves up and down only, the guard knot follows
+        let mut cut = Cut::default();
+        cut.toggle_cut_knot(1);
+        let (cut_initial, fader_initial) = lanes(&cut);
+        cut.move_selected(&cut_initial, &fader_initial, vec2(0.5, 0.5), 1.0);
+        assert_eq!(cut.cut_knots()[0].pos, pos2(-0.25, 0.5));
+        assert_eq!(cut.cut_knots()[1].pos, pos2(0.0, 0.5));
+    }
+
+    #[test]
+    fn move_cut_knot() {
+        let mut cut = Cut::default();
+        let before = lanes(&cut);
+        let initial = IndexKnot {
+            index: 2,
+            knot: cut.cut_knots()[2],
+        };
+        cut.move_cut_knot(2, pos2(1.53, 0.7));
+        assert_eq!(cut.cut_knots()[2].pos, pos2(1.5, 0.7));
+        cut.push_checkpoint(vec![CheckPointData::CutKnot(initial)]);
+        undo_redo(&mut cut, &before);
+
+        // not onto or past the neighbours, y is clamped
+        cut.move_cut_knot(2, pos2(2.0, 1.5));
+        assert_eq!(cut.cut_knots()[2].pos, pos2(1.5, 1.0));
+        cut.move_cut_knot(2, pos2(0.0, -0.5));
+        assert_eq!(cut.cut_knots()[2].pos, pos2(1.5, 0.0));
+        cut.move_cut_knot(2, pos2(0.0625, 0.2));
+        assert_eq!(cut.cut_knots()[2].pos, pos2(0.0625, 0.2));
+
+        // the end points keep their x
+        cut.move_cut_knot(1, pos2(0.5, 0.3));
+        assert_eq!(cut.cut_knots()[1].pos, pos2(0.0, 0.3));
+        assert_eq!(cut.cut_knots()[0].pos, pos2(-0.25, 0.3));
+        cut.move_cut_knot(3, pos2(1.0, 0.6));
+        assert_eq!(cut.cut_knots()[3].pos, pos2(2.0, 0.6));
+        assert_eq!(cut.cut_knots()[4].pos, pos2(2.25, 0.6));
+    }
+
+    #[test]
+    fn move_fader_knot() {
+        let mut cut = Cut::default();
+        let before = lanes(&cut);
+        let initial = IndexKnot {
+            index: 1,
+            knot: cut.fader_knots()[1],
+        };
+        cut.move_fader_knot(1, pos2(0.49, 0.5));
+        assert_eq!(cut.fader_knots()[1].pos, pos2(0.5, 0.5));
+        cut.push_checkpoint(vec![CheckPointData::FaderKnot(initial)]);
+        undo_redo(&mut cut, &before);
+
+        // onto, not past the neighbours, y is clamped
+        cut.move_fader_knot(1, pos2(1.5, 2.0));
+        assert_eq!(cut.fader_knots()[1].pos, pos2(0.5, 1.0));
+        cut.move_fader_knot(1, pos2(1.0, -1.0));
+        assert_eq!(cut.fader_knots()[1].pos, pos2(1.0, 0.0));
+        cut.move_fader_knot(1, pos2(-1.0, 0.0));
+        assert_eq!(cut.fader_knots()[1].pos, pos2(1.0, 0.0));
+        cut.move_fader_knot(1, pos2(0.0, 0.0));
+        assert_eq!(cut.fader_knots()[1].pos, pos2(0.0, 0.0));
+
+        // the first and last knot keep their x
+        cut.move_fader_knot(0, pos2(0.5, 1.0));
+        assert_eq!(cut.fader_knots()[0].pos, pos2(0.0, 1.0));
+        cut.move_fader_knot(5, pos2(1.75, 1.0));
+        assert_eq!(cut.fader_knots()[5].pos, pos2(2.0, 1.0));
+    }
+
+    #[test]
+    fn insert_cut_knot() {
+        let mut cut = Cut::default();
+        let before = lanes(&cut);
+        let checkpoint = cut.insert_cut_knot(pos2(0.51, 0.25));
+        cut.push_checkpoint(vec![checkpoint]);
+        assert_eq!(cut.cut_knots()[2].pos, pos2(0.5, 0.25));
+        assert_eq!(cut.cut_knots().len(), 6);
+        undo_redo(&mut cut, &before);
+
+        // a knot at the same x is moved
+        cut.insert_cut_knot(pos2(0.5, 0.75));
+        assert_eq!(cut.cut_knots()[2].pos, pos2(0.5, 0.75));
+        assert_eq!(cut.cut_knots().len(), 6);
+
+        // nothing left to redo
+        let after = lanes(&cut);
+        cut.redo();
+        assert_eq!(lanes(&cut), after);
+    }
+
+    #[test]
+    fn insert_fader_knot() {
+        let mut cut = Cut::default();
+        let before = lanes(&cut);
+        let checkpoint = cut.insert_fader_knot(pos2(0.49, 1.0));
+        cut.push_checkpoint(vec![checkpoint]);
+        assert_eq!(
+            positions(cut.fader_knots())[..3],
+            [pos2(0.0, 0.0), pos2(0.5, 1.0), pos2(1.0, 0.0)]
+        );
+        undo_redo(&mut cut, &before);
+
+        // a knot at the same x is added after it, a hard cut
+        cut.insert_fader_knot(pos2(0.5, 0.0));
+        assert_eq!(
+            positions(cut.fader_knots())[1..3],
+            [pos2(0.5, 1.0), pos2(0.5, 0.0)]
+        );
+        assert_eq!(cut.sample_fader(0.5), Some(0.0));
+        assert!(cut.validate().is_ok());
+    }
+
+    #[test]
+    fn set_bars_stretch() {
+        let mut cut = Cut::default();
+        let checkpoint = cut.set_bars(4.0, Resize::Stretch);
+        assert_eq!(checkpoint.len(), 3);
+        assert_eq!(cut.get_bars(), 4.0);
+        assert_eq!(xs(cut.cut_knots()), [-0.25, 0.0, 2.0, 4.0, 4.25]);
+        assert_eq!(xs(cut.fader_knots()), [0.0, 2.0, 2.0, 3.0, 3.0, 4.0]);
+        assert_eq!(cut.cut_knots()[2].pos.y, 0.5);
+        assert!(cut.validate().is_ok());
+    }
+
+    #[test]
+    fn set_bars_extend() {
+        let mut cut = Cut::default();
+        cut.set_bars(3.0, Resize::ExtendTrim);
+        assert_eq!(xs(cut.cut_knots()), [-0.25, 0.0, 1.0, 2.0, 3.0, 3.25]);
+        // the end values are held
+        assert_eq!(cut.cut_knots()[4].pos.y, 1.0);
+        assert_eq!(xs(cut.fader_knots()), [0.0, 1.0, 1.0, 1.5, 1.5, 2.0, 3.0]);
+        assert_eq!(cut.fader_knots()[6].pos.y, 0.0);
+        assert!(cut.validate().is_ok());
+    }
+
+    #[test]
+    fn set_bars_trim() {
+        let mut cut = Cut::default();
+        cut.set_bars(1.25, Resize::ExtendTrim);
+        assert_eq!(xs(cut.cut_knots()), [-0.25, 0.0, 1.0, 1.25, 1.5]);
+        assert_eq!(xs(cut.fader_knots()), [0.0, 1.0, 1.0, 1.25]);
+        // the fader is open at the new end
+        assert_eq!(cut.fader_knots()[3].pos.y, 1.0);
+        assert!(cut.validate().is_ok());
+    }
+
+    #[test]
+    fn set_bars_invalid() {
+        let mut cut = Cut::default();
+        for bars in [0.0, -1.0, f32::NAN, f32::INFINITY, 2.0] {
+            assert!(cut.set_bars(bars, Resize::Stretch).is_empty());
+        }
+        assert_eq!(cut.get_bars(), 2.0);
+    }
+
+    #[test]
+    fn undo_redo_bars() {
+        let mut cut = Cut::default();
+        let (cut_knots, fader_knots) = (positions(cut.cut_knots()), positions(cut.fader_knots()));
+
+        let checkpoint = cut.set_bars(4.0, Resize::Stretch);
+        cut.push_checkpoint(checkpoint);
+        let stretched = (positions(cut.cut_knots()), positions(cut.fader_knots()));
+
+        cut.undo();
+        assert_eq!(cut.get_bars(), 2.0);
+        assert_eq!(positions(cut.cut_knots()), cut_knots);
+        assert_eq!(positions(cut.fader_knots()), fader_knots);
+
+        cut.redo();
+        assert_eq!(cut.get_bars(), 4.0);
+        assert_eq!(positions(cut.cut_knots()), stretched.0);
+        assert_eq!(positions(cut.fader_knots()), stretched.1);
+    }
+
+    #[test]
+    fn load_path_project_rate() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("audio/sine.wav");
+        let (cut, _) = Cut::load_path(path.clone(), None).unwrap();
+        let rate = cut.wav_data.get_rate();
+        let to = if rate == 48000 { 44100 } else { 48000 };
+
+        // converted while loading, the window in samples of the new rate
+        let (cut, _) = Cut::load_path(path, Some(to)).unwrap();
+        assert_eq!(cut.wav_data.get_rate(), to);
+        assert_eq!(cut.wav_data.get_source_rate(), Some(rate));
+        assert_eq!(cut.wav.get_data_len(), cut.wav_data.len);
+    }
+
+    #[test]
+    fn interpolation() {
+        let mut cut = Cut::default();
+        let before = lanes(&cut);
+        let checkpoint = cut.set_interpolation(1, Interpolation::Linear);
+        cut.push_checkpoint(checkpoint);
+        assert_eq!(cut.spline_value(0.25), Some(0.125));
+        assert_eq!(cut.spline_value(0.5), Some(0.25));
+        undo_redo(&mut cut, &before);
+
+        // the selected knots too
+        cut.toggle_cut_knot(2);
+        cut.set_interpolation(1, Interpolation::Step);
+        assert_eq!(cut.spline_value(0.75), Some(0.0));
+        assert_eq!(cut.spline_value(1.5), Some(0.5));
+
+        cut.set_interpolation(1, Interpolation::Cosine);
+        let y = cut.spline_value(0.25).unwrap();
+        assert!((y - (1.0 - std::f32::consts::FRAC_PI_4.cos()) / 4.0).abs() < 1e-6);
+        assert!((cut.spline_value(0.5).unwrap() - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn monotone_tangents_flat() {
+        let keys: Vec<_> = [(0.0, 0.0), (1.0, 1.0), (2.0, 1.0), (3.0, 0.0)]
+            .iter()
+            .map(|(t, v)| splines::Key::new(*t, *v, splines::Interpolation::CatmullRom))
+            .collect();
+        // flat next to a flat segment and at extrema
+        assert_eq!(monotone_tangents(&keys), [1.0, 0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn monotone_tangents_limited() {
+        let keys: Vec<_> = [(0.0, 0.0), (1.0, 0.1), (2.0, 1.0), (3.0, 1.1)]
+            .iter()
+            .map(|(t, v)| splines::Key::new(*t, *v, splines::Interpolation::CatmullRom))
+            .collect();
+        let m = monotone_tangents(&keys);
+        for (i, k) in keys.windows(2).enumerate() {
+            let d = (k[1].value - k[0].value) / (k[1].t - k[0].t);
+            let (a, b) = (m[i] / d, m[i + 1] / d);
+            assert!(a >= 0.0 && b >= 0.0);
+            assert!(a * a + b * b <= 9.0 + 1e-4);
+        }
+        assert!(monotone_tangents(&keys[..1]) == [0.0]);
+    }
+
+    #[test]
+    fn monotone_no_overshoot() {
+        let mut cut = Cut::default();
+        cut.set_cut_knots(knots(&[
+            (-0.25, 0.0),
+            (0.0, 0.0),
+            (0.25, 1.0),
+            (1.0, 1.0),
+            (1.25, 0.0),
+            (2.0, 0.0),
+            (2.25, 0.0),
+        ]));
+        let range = |cut: &Cut| {
+            (0..200)
+                .filter_map(|i| cut.sample_spline(i as f32 / 100.0))
+                .fold((f32::MAX, f32::MIN), |(lo, hi), y| (lo.min(y), hi.max(y)))
+        };
+        let (lo, hi) = range(&cut);
+        assert!(lo < 0.0 || hi > 1.0, "catmull-rom overshoots");
+
+        cut.set_monotone(true);
+        let (lo, hi) = range(&cut);
+        assert!(lo >= 0.0 && hi <= 1.0);
+    }
+
+    #[test]
+    fn bezier_handles() {
+        let mut cut = Cut::default();
+        let before = lanes(&cut);
+        let checkpoint = cut.set_handles(2, true);
+        cut.push_checkpoint(checkpoint);
+        assert_eq!(cut.cut_knots()[2].handles, Some(Handles::default()));
+        // flat at the knot, the control points are the knot values
+        assert_eq!(cut.spline_value(0.5), Some(0.25));
+        assert_eq!(cut.spline_value(1.5), Some(0.75));
+        undo_redo(&mut cut, &before);
+
+        // the handles pull the segments on either side
+        cut.move_handle(2, true, 0.75);
+        cut.move_handle(2, false, 0.25);
+        assert_eq!(
+            cut.handle_positions(2),
+            Some((pos2(1.0 - 1.0 / 3.0, 0.25), pos2(1.0 + 1.0 / 3.0, 0.75)))
+        );
+        assert_eq!(cut.spline_value(0.5), Some(0.15625));
+        assert_eq!(cut.spline_value(1.5), Some(0.84375));
+        // through the knot
+        assert_eq!(cut.spline_value(1.0), Some(0.5));
+
+        cut.set_handles(2, false);
+        assert_eq!(cut.handle_positions(2), None);
+        assert_ne!(cut.spline_value(1.5), Some(0.84375));
+    }
+
+    #[test]
+    fn fader_curve_gain() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        assert_eq!(FaderCurve::Sharp.gain(0.0), 0.0);
+        assert_eq!(FaderCurve::Sharp.gain(0.01), 1.0);
+        assert_eq!(FaderCurve::Linear.gain(0.25), 0.25);
+        assert!(close(FaderCurve::ConstantPower.gain(0.5), 0.5f32.sqrt()));
+        assert_eq!(FaderCurve::ConstantPower.gain(1.0), 1.0);
+        assert_eq!(FaderCurve::Exponent(2.0).gain(0.5), 0.25);
+        for curve in FaderCurve::ALL {
+            assert_eq!(curve.gain(0.0), 0.0);
+            assert_eq!(curve.gain(1.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn fader_lag() {
+        let mut cut = Cut::default();
+        // closed up to the lag, the rest of the travel is scaled to the curve
+        cut.set_fader_curve(FaderCurve::Exponent(2.0), 0.25);
+        assert_eq!(cut.fader_gain(0.25), 0.0);
+        assert_eq!(cut.fader_gain(0.625), 0.25);
+        assert_eq!(cut.fader_gain(1.0), 1.0);
+        cut.set_fader_curve(FaderCurve::Sharp, 0.25);
+        assert_eq!(cut.fader_gain(0.25), 0.0);
+        assert_eq!(cut.fader_gain(0.3), 1.0);
+
+        // halfway up the fader ramp at 0.25
+        cut.set_fader_knots(knots(&[(0.0, 0.0), (0.5, 1.0), (2.0, 1.0)]));
+        cut.set_fader_curve(FaderCurve::Linear, 0.5);
+        assert_eq!(cut.sample_gain(0.25), Some(0.0));
+        assert_eq!(cut.sample_gain(0.375), Some(0.5));
+        assert_eq!(cut.sample_gain(1.0), Some(1.0));
+
+        // the lag is limited
+        cut.set_fader_curve(FaderCurve::Linear, 0.8);
+        assert_eq!(cut.get_fader_curve(), (FaderCurve::Linear, 0.5));
+        assert!(cut.validate().is_ok());
+    }
+
+    #[test]
+    fn insert_knots() {
+        let mut cut = Cut::default();
+        let (cut_knots, fader_knots) = (positions(cut.cut_knots()), positions(cut.fader_knots()));
+
+        let checkpoint = cut.insert_knots(
+            knots(&[(0.5, 0.2), (0.75, 0.8), (1.5, 0.2)]),
+            knots(&[(0.5, 1.0), (1.5, 1.0)]),
+        );
+        cut.push_checkpoint(checkpoint);
+        assert_eq!(xs(cut.cut_knots()), [-0.25, 0.0, 0.5, 0.75, 1.5, 2.0, 2.25]);
+        // the fader cuts in and back to its old value
+        assert_eq!(
+            positions(cut.fader_knots()),
+            [
+                pos2(0.0, 0.0),
+                pos2(0.5, 0.0),
+                pos2(0.5, 1.0),
+                pos2(1.5, 1.0),
+                pos2(1.5, 0.0),
+                pos2(2.0, 0.0),
+            ]
+        );
+        assert!(cut.validate().is_ok());
+
+        // a single undo step
+        cut.undo();
+        assert_eq!(positions(cut.cut_knots()), cut_knots);
+        assert_eq!(positions(cut.fader_knots()), fader_knots);
+    }
+
+    #[test]
+    fn insert_knots_end_points() {
+        let mut cut = Cut::default();
+        cut.insert_knots(
+            knots(&[(0.0, 0.3), (1.0, 0.9), (2.0, 0.3)]),
+            knots(&[(0.0, 1.0), (2.0, 1.0)]),
+        );
+        // the end points keep their x and take the y
+        assert_eq!(
+            positions(cut.cut_knots()),
+            [
+                pos2(-0.25, 0.3),
+                pos2(0.0, 0.3),
+                pos2(1.0, 0.9),
+                pos2(2.0, 0.3),
+                pos2(2.25, 0.3),
+            ]
+        );
+        assert_eq!(
+            positions(cut.fader_knots()),
+            [pos2(0.0, 1.0), pos2(2.0, 1.0)]
+        );
+        assert!(cut.validate().is_ok());
+        assert!(cut.insert_knots(vec![], vec![]).is_empty());
+    }
+}
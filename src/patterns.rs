@@ -0,0 +1,366 @@
+// patterns
+//
+// Generators of classic scratch techniques. A pattern repeats a stroke, a
+// forward and a back movement of the record, at a rate of strokes per bar.
+// Each stroke is described by cut points (position in the stroke, record
+// position between low and high) and fader points (position in the stroke,
+// 1.0 open, 0.0 closed). Fader points sharing a position are hard cuts.
+use crate::{
+    grid::quantization_name,
+    model::{Interpolation, Knot},
+};
+use egui::{pos2, ComboBox, DragValue, Slider, Ui};
+
+/// Stroke rates, in strokes per bar
+pub const RATES: [u32; 7] = [1, 2, 4, 8, 16, 6, 12];
+
+/// Fader closed for a click, in strokes
+const CLICK: f32 = 1.0 / 16.0;
+
+/// Scratch technique
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Pattern {
+    /// Forward and back, fader open
+    #[default]
+    Baby,
+    /// Forward stroke heard, back stroke cut
+    Forward,
+    /// Fader closes halfway into the forward stroke, opens halfway into the back stroke
+    Chirp,
+    /// Baby stroke, fader tapped open and closed
+    Transformer,
+    /// Forward stroke cut in two by a click, fader open
+    Flare1,
+    /// Forward stroke cut in three by two clicks, fader open
+    Flare2,
+    /// Four fast taps open during the forward stroke, fader closed
+    Crab,
+    /// One click flare on the forward and the back stroke
+    Orbit,
+    /// Forward stroke, back stroke in two pulls with a pause
+    Tear,
+    /// Fast short back and forth near low, fader open
+    Scribble,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 10] = [
+        Pattern::Baby,
+        Pattern::Forward,
+        Pattern::Chirp,
+        Pattern::Transformer,
+        Pattern::Flare1,
+        Pattern::Flare2,
+        Pattern::Crab,
+        Pattern::Orbit,
+        Pattern::Tear,
+        Pattern::Scribble,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Baby => "baby",
+            Pattern::Forward => "forward",
+            Pattern::Chirp => "chirp",
+            Pattern::Transformer => "transformer",
+            Pattern::Flare1 => "flare (1-click)",
+            Pattern::Flare2 => "flare (2-click)",
+            Pattern::Crab => "crab",
+            Pattern::Orbit => "orbit",
+            Pattern::Tear => "tear",
+            Pattern::Scribble => "scribble",
+        }
+    }
+
+    /// Cut points of one stroke, from 0.0 up to (not including) 1.0, the
+    /// stroke ends where it starts. A hold is marked true, it is linear to the
+    /// next point.
+    fn cut_points(&self) -> Vec<(f32, f32, bool)> {
+        match self {
+            Pattern::Tear => vec![
+                (0.0, 0.0, false),
+                (0.5, 1.0, false),
+                (0.625, 0.5, true),
+                (0.75, 0.5, false),
+            ],
+            Pattern::Scribble => (0..8)
+                .map(|i| (i as f32 / 8.0, if i % 2 == 0 { 0.0 } else { 0.1 }, false))
+                .collect(),
+            _ => vec![(0.0, 0.0, false), (0.5, 1.0, false)],
+        }
+    }
+
+    /// Fader points of one stroke, from 0.0 to 1.0
+    fn fader_points(&self) -> Vec<(f32, f32)> {
+        // fader closed from start to end
+        let click = |points: &mut Vec<(f32, f32)>, start: f32, end: f32| {
+            points.extend([(start, 1.0), (start, 0.0), (end, 0.0), (end, 1.0)])
+        };
+        // fader open n times in the first len of the stroke, closed otherwise
+        let taps = |n: usize, len: f32| {
+            let mut points = vec![];
+            for i in 0..n {
+                let start = i as f32 * len / n as f32;
+                let end = start + len / n as f32 / 2.0;
+                points.extend([(start, 0.0), (start, 1.0), (end, 1.0), (end, 0.0)]);
+            }
+            points.push((1.0, 0.0));
+            points
+        };
+
+        let mut points = vec![(0.0, 1.0)];
+        match self {
+            Pattern::Baby | Pattern::Tear | Pattern::Scribble => {}
+            Pattern::Forward => points.extend([(0.5, 1.0), (0.5, 0.0), (1.0, 0.0)]),
+            Pattern::Chirp => click(&mut points, 0.25, 0.75),
+            Pattern::Transformer => return taps(8, 1.0),
+            Pattern::Flare1 => click(&mut points, 0.25 - CLICK / 2.0, 0.25 + CLICK / 2.0),
+            Pattern::Flare2 => {
+                for center in [0.5 / 3.0, 1.0 / 3.0] {
+                    click(&mut points, center - CLICK / 2.0, center + CLICK / 2.0);
+                }
+            }
+            Pattern::Crab => return taps(4, 0.5),
+            Pattern::Orbit => {
+                for center in [0.25, 0.75] {
+                    click(&mut points, center - CLICK / 2.0, center + CLICK / 2.0);
+                }
+            }
+        }
+        if points.last().map(|(x, _)| *x) != Some(1.0) {
+            points.push((1.0, 1.0));
+        }
+        points
+    }
+}
+
+/// Settings of the pattern generator
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternSettings {
+    pub pattern: Pattern,
+
+    /// Start in bars
+    pub start: f32,
+
+    /// Length in bars, rounded down to whole strokes
+    pub bars: f32,
+
+    /// Strokes per bar
+    pub rate: u32,
+
+    /// Record position at the start of a stroke, 0.0..=1.0 of the wav window
+    pub low: f32,
+
+    /// Record position at the end of the forward stroke
+    pub high: f32,
+}
+
+impl Default for PatternSettings {
+    fn default() -> Self {
+        PatternSettings {
+            pattern: Pattern::default(),
+            start: 0.0,
+            bars: 1.0,
+            rate: 4,
+            low: 0.0,
+            high: 0.5,
+        }
+    }
+}
+
+impl PatternSettings {
+    /// Cut and fader knots of the pattern, within a cut of cut_bars
+    pub fn generate(&self, cut_bars: f32) -> Result<(Vec<Knot>, Vec<Knot>), String> {
+        let start = self.start.clamp(0.0, cut_bars);
+        let len = 1.0 / self.rate.max(1) as f32;
+        let strokes = (((self.bars.min(cut_bars - start)) / len) + 1e-4).floor() as usize;
+        if strokes == 0 {
+            Err("Pattern shorter than one stroke")?;
+        }
+        let y = |y: f32| (self.low + y * (self.high - self.low)).clamp(0.0, 1.0);
+
+        let mut cut_knots = vec![];
+        let mut fader_knots: Vec<Knot> = vec![];
+        // position of x in stroke, strokes join exactly
+        let t = |stroke: usize, x: f32| start + (stroke as f32 + x) * len;
+        for stroke in 0..strokes {
+            for (x, value, hold) in self.pattern.cut_points() {
+                let mut knot = Knot::new(pos2(t(stroke, x), y(value)));
+                if hold {
+                    knot.interpolation = Interpolation::Linear;
+                }
+                cut_knots.push(knot);
+            }
+            for (x, value) in self.pattern.fader_points() {
+                let knot = Knot::new(pos2(t(stroke, x), value));
+                // strokes join at the same gain
+                if fader_knots.last().map(|k| k.pos) != Some(knot.pos) {
+                    fader_knots.push(knot);
+                }
+            }
+        }
+        let first = self.pattern.cut_points()[0];
+        cut_knots.push(Knot::new(pos2(t(strokes, 0.0), y(first.1))));
+        Ok((cut_knots, fader_knots))
+    }
+
+    /// Pattern generator panel
+    pub fn ui_content(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Pattern");
+            ComboBox::from_id_source("pattern")
+                .selected_text(self.pattern.name())
+                .show_ui(ui, |ui| {
+                    for pattern in Pattern::ALL {
+                        ui.selectable_value(&mut self.pattern, pattern, pattern.name());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Start");
+            ui.add(
+                DragValue::new(&mut self.start)
+                    .range(0.0..=64.0)
+                    .speed(0.05)
+                    .fixed_decimals(2),
+            );
+            ui.label("Bars");
+            ui.add(
+                DragValue::new(&mut self.bars)
+                    .range(0.0..=64.0)
+                    .speed(0.05)
+                    .fixed_decimals(2),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Rate");
+            ComboBox::from_id_source("pattern_rate")
+                .selected_text(quantization_name(self.rate))
+                .show_ui(ui, |ui| {
+                    for rate in RATES {
+                        ui.selectable_value(&mut self.rate, rate, quantization_name(rate));
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Low");
+            ui.add(Slider::new(&mut self.low, 0.0..=1.0));
+        });
+        ui.horizontal(|ui| {
+            ui.label("High");
+            ui.add(Slider::new(&mut self.high, 0.0..=1.0));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Pos2;
+
+    fn positions(knots: &[Knot]) -> Vec<Pos2> {
+        knots.iter().map(|k| k.pos).collect()
+    }
+
+    #[test]
+    fn all_patterns() {
+        for pattern in Pattern::ALL {
+            for rate in RATES {
+                let settings = PatternSettings {
+                    pattern,
+                    start: 0.5,
+                    bars: 1.0,
+                    rate,
+                    low: 0.2,
+                    high: 0.6,
+                };
+                let (cut_knots, fader_knots) = settings.generate(2.0).unwrap();
+                let end = 0.5 + (rate as f32).recip() * rate as f32;
+
+                assert_eq!(cut_knots[0].pos.x, 0.5);
+                assert!((cut_knots.last().unwrap().pos.x - end).abs() < 1e-5);
+                assert!(cut_knots.windows(2).all(|k| k[0].pos.x < k[1].pos.x));
+                assert!(cut_knots.iter().all(|k| (0.2..=0.6).contains(&k.pos.y)));
+
+                assert_eq!(fader_knots[0].pos.x, 0.5);
+                assert!((fader_knots.last().unwrap().pos.x - end).abs() < 1e-5);
+                assert!(fader_knots.windows(2).all(|k| k[0].pos.x <= k[1].pos.x));
+                assert!(fader_knots.windows(2).all(|k| k[0].pos != k[1].pos));
+                assert!(fader_knots.iter().all(|k| (0.0..=1.0).contains(&k.pos.y)));
+            }
+        }
+    }
+
+    #[test]
+    fn baby() {
+        let settings = PatternSettings {
+            rate: 2,
+            ..PatternSettings::default()
+        };
+        let (cut_knots, fader_knots) = settings.generate(2.0).unwrap();
+        assert_eq!(
+            positions(&cut_knots),
+            [
+                pos2(0.0, 0.0),
+                pos2(0.25, 0.5),
+                pos2(0.5, 0.0),
+                pos2(0.75, 0.5),
+                pos2(1.0, 0.0),
+            ]
+        );
+        assert_eq!(
+            positions(&fader_knots),
+            [pos2(0.0, 1.0), pos2(0.5, 1.0), pos2(1.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn forward() {
+        let settings = PatternSettings {
+            pattern: Pattern::Forward,
+            rate: 2,
+            ..PatternSettings::default()
+        };
+        let (_, fader_knots) = settings.generate(2.0).unwrap();
+        // hard cuts at the turn and at the start of the next stroke
+        assert_eq!(
+            positions(&fader_knots),
+            [
+                pos2(0.0, 1.0),
+                pos2(0.25, 1.0),
+                pos2(0.25, 0.0),
+                pos2(0.5, 0.0),
+                pos2(0.5, 1.0),
+                pos2(0.75, 1.0),
+                pos2(0.75, 0.0),
+                pos2(1.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn whole_strokes() {
+        // 0.7 bars left in the cut, two quarter strokes
+        let settings = PatternSettings {
+            start: 0.5,
+            bars: 1.0,
+            ..PatternSettings::default()
+        };
+        let (cut_knots, _) = settings.generate(1.2).unwrap();
+        assert_eq!(cut_knots.last().unwrap().pos.x, 1.0);
+
+        let settings = PatternSettings {
+            bars: 0.2,
+            ..PatternSettings::default()
+        };
+        assert!(settings.generate(2.0).is_err());
+        let settings = PatternSettings {
+            start: 1.9,
+            ..PatternSettings::default()
+        };
+        assert!(settings.generate(2.0).is_err());
+    }
+}
@@ -0,0 +1,617 @@
+// playback
+//
+// Audition of cuts. A `Player` renders a cut (interleaved stereo f32) block by
+// block on a worker thread and streams it to a `Sink`, the audio device or a
+// memory/file sink.
+use crate::{model::Cut, render::RenderSettings, sinc::CutRenderer};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::{debug, trace};
+
+/// Frames per block
+pub const BLOCK_FRAMES: usize = 512;
+
+/// Poll interval of the worker when stopped
+const IDLE: Duration = Duration::from_millis(10);
+
+/// Output of the player, interleaved stereo f32
+pub trait Sink: Send {
+    /// Sample rate the sink plays at
+    fn sample_rate(&self) -> u32;
+
+    /// Write a block, may block to pace playback
+    fn write(&mut self, block: &[f32]) -> Result<(), String>;
+
+    /// Playback stopped
+    fn flush(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Discards the audio, paced in real time. Used when there is no audio device.
+pub struct ClockSink {
+    sample_rate: u32,
+    start: Option<Instant>,
+    frames: u64,
+}
+
+impl ClockSink {
+    pub fn new(sample_rate: u32) -> Self {
+        ClockSink {
+            sample_rate,
+            start: None,
+            frames: 0,
+        }
+    }
+}
+
+impl Sink for ClockSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, block: &[f32]) -> Result<(), String> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        self.frames += (block.len() / 2) as u64;
+        let due = start + Duration::from_secs_f64(self.frames as f64 / self.sample_rate as f64);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.start = None;
+        self.frames = 0;
+        Ok(())
+    }
+}
+
+/// Collects the audio in memory, not paced
+#[derive(Clone)]
+pub struct MemorySink {
+    sample_rate: u32,
+    samples: Arc<Mutex<Vec<f32>>>,
+}
+
+impl MemorySink {
+    pub fn new(sample_rate: u32) -> Self {
+        MemorySink {
+            sample_rate,
+            samples: Arc::default(),
+        }
+    }
+
+    /// Samples written so far, a clone of the sink sees the same samples
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.lock().unwrap().clone()
+    }
+}
+
+impl Sink for MemorySink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, block: &[f32]) -> Result<(), String> {
+        self.samples.lock().unwrap().extend_from_slice(block);
+        Ok(())
+    }
+}
+
+/// Writes the audio to a float WAV file, not paced
+pub struct WavSink {
+    sample_rate: u32,
+    writer: WavWriter<BufWriter<File>>,
+}
+
+impl WavSink {
+    pub fn new(path: &Path, sample_rate: u32) -> Result<Self, String> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let writer = WavWriter::create(path, spec).map_err(|err| format!("{:?}", err))?;
+        Ok(WavSink {
+            sample_rate,
+            writer,
+        })
+    }
+}
+
+impl Sink for WavSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, block: &[f32]) -> Result<(), String> {
+        for s in block {
+            self.writer
+                .write_sample(*s)
+                .map_err(|err| format!("{:?}", err))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(|err| format!("{:?}", err))
+    }
+}
+
+/// The default audio output device
+#[cfg(feature = "audio")]
+pub struct DeviceSink {
+    sample_rate: u32,
+    sender: std::sync::mpsc::SyncSender<Vec<f32>>,
+    // the stream lives in its own thread (it is not Send), dropping this ends it
+    _keep_alive: std::sync::mpsc::Sender<()>,
+}
+
+#[cfg(feature = "audio")]
+impl DeviceSink {
+    /// Open the default output device, at its default rate
+    pub fn new() -> Result<Self, String> {
+        use std::sync::mpsc;
+
+        // a few blocks of latency
+        let (sender, receiver) = mpsc::sync_channel::<Vec<f32>>(4);
+        let (init_sender, init_receiver) = mpsc::channel();
+        let (keep_alive, keep_alive_receiver) = mpsc::channel::<()>();
+        thread::spawn(move || match Self::open(receiver) {
+            Ok((stream, sample_rate)) => {
+                let _ = init_sender.send(Ok(sample_rate));
+                // blocks until the sink is dropped
+                let _ = keep_alive_receiver.recv();
+                drop(stream);
+            }
+            Err(err) => {
+                let _ = init_sender.send(Err(err));
+            }
+        });
+
+        let sample_rate = init_receiver
+            .recv()
+            .map_err(|_| "Audio device thread failed".to_string())??;
+        Ok(DeviceSink {
+            sample_rate,
+            sender,
+            _keep_alive: keep_alive,
+        })
+    }
+
+    fn open(receiver: std::sync::mpsc::Receiver<Vec<f32>>) -> Result<(cpal::Stream, u32), String> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("No audio output device")?;
+        let config = device
+            .default_output_config()
+            .map_err(|err| format!("Audio device: {}", err))?;
+        if config.sample_format() != cpal::SampleFormat::F32 {
+            Err(format!(
+                "Audio device format {} not supported",
+                config.sample_format()
+            ))?;
+        }
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+
+        let mut block: Vec<f32> = vec![];
+        let mut pos = 0;
+        let stream = device
+            .build_output_stream(
+                &config.config(),
+                move |data: &mut [f32], _| {
+                    for frame in data.chunks_mut(channels) {
+                        if pos >= block.len() {
+                            // underrun or stopped plays silence
+                            block = receiver.try_recv().unwrap_or_default();
+                            pos = 0;
+                        }
+                        let (left, right) = match block.get(pos..pos + 2) {
+                            Some(&[left, right]) => (left, right),
+                            _ => (0.0, 0.0),
+                        };
+                        pos += 2;
+                        for (channel, s) in frame.iter_mut().enumerate() {
+                            *s = match channel {
+                                0 => left,
+                                1 => right,
+                                _ => 0.0,
+                            };
+                        }
+                    }
+                },
+                |err| log::error!("audio stream {}", err),
+                None,
+            )
+            .map_err(|err| format!("Audio device: {}", err))?;
+        stream
+            .play()
+            .map_err(|err| format!("Audio device: {}", err))?;
+        Ok((stream, sample_rate))
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Sink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, block: &[f32]) -> Result<(), String> {
+        self.sender
+            .send(block.to_vec())
+            .map_err(|_| "Audio device closed".to_string())
+    }
+}
+
+/// The audio device, or a silent real time sink at sample_rate if there is none.
+/// The message describes the sink for the status bar.
+pub fn default_sink(sample_rate: u32) -> (Box<dyn Sink>, String) {
+    #[cfg(feature = "audio")]
+    let (sink, msg): (Box<dyn Sink>, String) = match DeviceSink::new() {
+        Ok(sink) => {
+            let msg = format!("Playing on audio device at {} Hz", sink.sample_rate());
+            (Box::new(sink), msg)
+        }
+        Err(err) => (
+            Box::new(ClockSink::new(sample_rate)),
+            format!("{}, playing silently", err),
+        ),
+    };
+    #[cfg(not(feature = "audio"))]
+    let (sink, msg): (Box<dyn Sink>, String) = (
+        Box::new(ClockSink::new(sample_rate)),
+        "Built without the audio feature, playing silently".to_string(),
+    );
+    (sink, msg)
+}
+
+/// State shared between the player and its worker
+#[derive(Default)]
+struct State {
+    /// renders the cut played, from a snapshot
+    renderer: Option<CutRenderer<Arc<Cut>>>,
+    playing: bool,
+    looping: bool,
+    error: Option<String>,
+    quit: bool,
+}
+
+impl State {
+    /// Next block from the renderer, stops at the end unless looping
+    fn next_block(&mut self, block: &mut Vec<f32>) {
+        let Some(renderer) = &mut self.renderer else {
+            self.playing = false;
+            return;
+        };
+        while block.len() < 2 * BLOCK_FRAMES && self.playing {
+            if renderer.is_finished() {
+                if self.looping && !renderer.is_empty() {
+                    renderer.reset();
+                } else {
+                    self.playing = false;
+                    break;
+                }
+            }
+            let start = block.len();
+            let frames = BLOCK_FRAMES - start / 2;
+            block.resize(start + 2 * frames, 0.0);
+            let rendered = renderer.process(&mut block[start..], frames);
+            block.truncate(start + 2 * rendered);
+        }
+    }
+}
+
+/// Renders blocks and writes them to the sink
+struct Worker {
+    state: Arc<Mutex<State>>,
+    sink: Box<dyn Sink>,
+    block: Vec<f32>,
+    was_playing: bool,
+}
+
+impl Worker {
+    /// Write the next block, or flush the sink when playback stopped. Returns
+    /// false when idle, None when the player quit.
+    fn step(&mut self) -> Option<bool> {
+        self.block.clear();
+        let playing = {
+            let mut state = self.state.lock().unwrap();
+            if state.quit {
+                return None;
+            }
+            state.next_block(&mut self.block);
+            state.playing
+        };
+
+        let busy = !self.block.is_empty() || self.was_playing;
+        let result = if !self.block.is_empty() {
+            self.sink.write(&self.block)
+        } else if self.was_playing {
+            debug!("playback stopped");
+            self.sink.flush()
+        } else {
+            Ok(())
+        };
+        trace!("block {} frames", self.block.len() / 2);
+        self.was_playing = playing || !self.block.is_empty();
+
+        if let Err(err) = result {
+            let mut state = self.state.lock().unwrap();
+            state.playing = false;
+            state.error = Some(err);
+        }
+        Some(busy)
+    }
+}
+
+/// Streams a cut to a sink, with play, stop and loop. The worker renders
+/// block by block from a snapshot of the cut, an edit swaps in a new one.
+pub struct Player {
+    state: Arc<Mutex<State>>,
+    sample_rate: u32,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Player {
+    /// Start the worker, writing to sink
+    pub fn new(sink: Box<dyn Sink>) -> Self {
+        let (mut player, mut worker) = Self::without_thread(sink);
+        player.worker = Some(thread::spawn(move || {
+            while let Some(busy) = worker.step() {
+                if !busy {
+                    thread::sleep(IDLE);
+                }
+            }
+        }));
+        player
+    }
+
+    /// The player and its worker, which is stepped by the caller
+    fn without_thread(sink: Box<dyn Sink>) -> (Self, Worker) {
+        let state = Arc::new(Mutex::new(State::default()));
+        let player = Player {
+            state: state.clone(),
+            sample_rate: sink.sample_rate(),
+            worker: None,
+        };
+        let worker = Worker {
+            state,
+            sink,
+            block: Vec::with_capacity(2 * BLOCK_FRAMES),
+            was_playing: false,
+        };
+        (player, worker)
+    }
+
+    /// Sample rate of the sink, cuts are rendered at this rate
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Play cut from the start, settings are used at the rate of the sink
+    pub fn play(&self, cut: Arc<Cut>, settings: &RenderSettings) -> Result<(), String> {
+        let renderer = self.renderer(cut, settings)?;
+        let mut state = self.state.lock().unwrap();
+        state.renderer = Some(renderer);
+        state.playing = true;
+        Ok(())
+    }
+
+    /// Replace the cut played, continuing at the same bar and fader gain, e.g. after an edit
+    pub fn update(&self, cut: Arc<Cut>, settings: &RenderSettings) -> Result<(), String> {
+        let mut renderer = self.renderer(cut, settings)?;
+        let mut state = self.state.lock().unwrap();
+        if let Some(old) = &state.renderer {
+            renderer.continue_from(old);
+        }
+        state.renderer = Some(renderer);
+        Ok(())
+    }
+
+    fn renderer(
+        &self,
+        cut: Arc<Cut>,
+        settings: &RenderSettings,
+    ) -> Result<CutRenderer<Arc<Cut>>, String> {
+        let settings = RenderSettings {
+            sample_rate: self.sample_rate,
+            ..settings.clone()
+        };
+        CutRenderer::new(cut, &settings)
+    }
+
+    /// Stop playing
+    pub fn stop(&self) {
+        self.state.lock().unwrap().playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state.lock().unwrap().playing
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.state.lock().unwrap().looping
+    }
+
+    /// Restart from the start at the end
+    pub fn set_looping(&self, looping: bool) {
+        self.state.lock().unwrap().looping = looping;
+    }
+
+    /// Position 0..1 in the cut while playing
+    pub fn position(&self) -> Option<f32> {
+        let state = self.state.lock().unwrap();
+        let renderer = state.renderer.as_ref()?;
+        (state.playing && !renderer.is_empty())
+            .then(|| renderer.frame() as f32 / renderer.len() as f32)
+    }
+
+    /// Error of the sink that stopped playback, if any
+    pub fn take_error(&self) -> Option<String> {
+        self.state.lock().unwrap().error.take()
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().quit = true;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::sine_cut, model::Knot, sinc};
+    use egui::pos2;
+
+    /// Samples of a block
+    const BLOCK: usize = 2 * BLOCK_FRAMES;
+
+    /// A player on a 48 kHz memory sink, the worker is stepped by the test
+    fn player() -> (Player, Worker, MemorySink) {
+        let sink = MemorySink::new(48000);
+        let (player, worker) = Player::without_thread(Box::new(sink.clone()));
+        (player, worker, sink)
+    }
+
+    /// bars of the sine cut, rendered at the rate of the sink
+    fn cut(bars: f32) -> (Arc<Cut>, RenderSettings) {
+        let (cut, settings) = sine_cut(bars);
+        let settings = RenderSettings {
+            sample_rate: 48000,
+            ..settings
+        };
+        (Arc::new(cut), settings)
+    }
+
+    #[test]
+    fn play() {
+        let (player, mut worker, sink) = player();
+        let (cut, settings) = cut(0.05);
+        let expected = sinc::render(&cut, &settings).unwrap();
+        assert!(expected.iter().any(|s| *s != 0.0));
+
+        player.play(cut, &settings).unwrap();
+        for _ in 0..expected.len().div_ceil(BLOCK) {
+            assert!(player.is_playing());
+            assert_eq!(worker.step(), Some(true));
+        }
+        assert!(!player.is_playing());
+        assert_eq!(sink.samples(), expected);
+
+        // flushed, then idle
+        assert_eq!(worker.step(), Some(true));
+        assert_eq!(worker.step(), Some(false));
+        assert_eq!(sink.samples().len(), expected.len());
+        assert_eq!(player.take_error(), None);
+    }
+
+    #[test]
+    fn stop() {
+        let (player, mut worker, sink) = player();
+        let (cut, settings) = cut(8.0);
+        player.play(cut, &settings).unwrap();
+        for _ in 0..3 {
+            worker.step();
+        }
+        assert_eq!(sink.samples().len(), 3 * BLOCK);
+
+        player.stop();
+        assert!(!player.is_playing());
+        assert_eq!(worker.step(), Some(true));
+        assert_eq!(worker.step(), Some(false));
+        assert_eq!(sink.samples().len(), 3 * BLOCK);
+    }
+
+    #[test]
+    fn looping() {
+        let (player, mut worker, sink) = player();
+        let (cut, settings) = cut(0.05);
+        let expected = sinc::render(&cut, &settings).unwrap();
+        let len = expected.len();
+
+        player.set_looping(true);
+        player.play(cut, &settings).unwrap();
+        let blocks = (3 * len).div_ceil(BLOCK);
+        for _ in 0..blocks {
+            worker.step();
+        }
+        assert!(player.is_playing());
+
+        // blocks are filled across the restart
+        let samples = sink.samples();
+        assert_eq!(samples.len(), blocks * BLOCK);
+        for period in samples.chunks_exact(len).take(3) {
+            assert_eq!(period, expected);
+        }
+    }
+
+    #[test]
+    fn position() {
+        let (player, mut worker, _) = player();
+        let (cut, settings) = cut(8.0);
+        let len = CutRenderer::new(&*cut, &settings).unwrap().len();
+        assert_eq!(player.position(), None);
+
+        player.play(cut, &settings).unwrap();
+        assert_eq!(player.position(), Some(0.0));
+        worker.step();
+        worker.step();
+        assert_eq!(
+            player.position(),
+            Some((2 * BLOCK_FRAMES) as f32 / len as f32)
+        );
+
+        player.stop();
+        assert_eq!(player.position(), None);
+    }
+
+    #[test]
+    fn update() {
+        let (player, mut worker, sink) = player();
+        let (cut, settings) = cut(0.05);
+        let expected = sinc::render(&cut, &settings).unwrap();
+
+        // the same cut continues where it was
+        player.play(cut.clone(), &settings).unwrap();
+        worker.step();
+        let position = player.position();
+        player.update(cut.clone(), &settings).unwrap();
+        assert_eq!(player.position(), position);
+        while player.is_playing() {
+            worker.step();
+        }
+        assert_eq!(sink.samples(), expected);
+
+        // an edit is heard from the next block, the fader closes
+        let (cut, settings) = self::cut(0.05);
+        let mut closed = cut.snapshot();
+        closed.set_fader_knots(vec![Knot::new(pos2(0.0, 0.0)), Knot::new(pos2(2.0, 0.0))]);
+        player.play(cut, &settings).unwrap();
+        worker.step();
+        player.update(Arc::new(closed), &settings).unwrap();
+        worker.step();
+        let samples = sink.samples();
+        let block = &samples[samples.len() - BLOCK..];
+        // after the anti-click ramp
+        assert!(block[BLOCK / 2..].iter().all(|s| *s == 0.0));
+        assert!(block[..2].iter().any(|s| *s != 0.0));
+    }
+}
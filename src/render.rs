@@ -0,0 +1,216 @@
+// render settings
+use crate::sinc::Quality;
+use egui::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Sample format of the rendered file
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    pub const ALL: [BitDepth; 3] = [BitDepth::Int16, BitDepth::Int24, BitDepth::Float32];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BitDepth::Int16 => "16 bit int",
+            BitDepth::Int24 => "24 bit int",
+            BitDepth::Float32 => "32 bit float",
+        }
+    }
+
+    /// hound spec for a stereo file in this format
+    pub fn wav_spec(&self, sample_rate: u32) -> hound::WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            BitDepth::Int16 => (16, hound::SampleFormat::Int),
+            BitDepth::Int24 => (24, hound::SampleFormat::Int),
+            BitDepth::Float32 => (32, hound::SampleFormat::Float),
+        };
+        hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+/// Settings used when rendering a cut to audio.
+///
+/// Tempo is given in quarter notes per minute, so a bar lasts
+/// `beats_per_bar * 4 / beat_unit` quarter notes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    /// Tempo, quarter notes per minute
+    pub bpm: f32,
+
+    /// Time signature numerator
+    pub beats_per_bar: u32,
+
+    /// Time signature denominator
+    pub beat_unit: u32,
+
+    /// Output sample rate
+    pub sample_rate: u32,
+
+    /// Output sample format
+    pub bit_depth: BitDepth,
+
+    /// Resampling quality
+    pub quality: Quality,
+
+    /// Destination, if None derived from the cut path
+    pub path: Option<PathBuf>,
+
+    /// Length override in bars, if None the cut length is used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bars: Option<f32>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            bpm: 120.0,
+            beats_per_bar: 4,
+            beat_unit: 4,
+            sample_rate: 48000,
+            bit_depth: BitDepth::Float32,
+            quality: Quality::default(),
+            path: None,
+            bars: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Check the settings give a render, tempo, rate, time signature and bars above 0
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.bpm > 0.0 && self.bpm.is_finite()) {
+            Err(format!("Invalid tempo {}", self.bpm))?;
+        }
+        if self.sample_rate == 0 {
+            Err("Invalid sample rate 0")?;
+        }
+        if self.beats_per_bar == 0 || self.beat_unit == 0 {
+            Err(format!(
+                "Invalid time signature {}/{}",
+                self.beats_per_bar, self.beat_unit
+            ))?;
+        }
+        if let Some(bars) = self.bars.filter(|bars| !(*bars > 0.0 && bars.is_finite())) {
+            Err(format!("Invalid length {} bars", bars))?;
+        }
+        Ok(())
+    }
+
+    /// Duration of one bar in seconds
+    pub fn bar_seconds(&self) -> f32 {
+        self.beats_per_bar as f32 * 4.0 / self.beat_unit as f32 * 60.0 / self.bpm
+    }
+
+    /// Destination path, `<cut>_render.wav` next to the cut unless set
+    pub fn output_path(&self, cut_path: &Path) -> PathBuf {
+        match &self.path {
+            Some(path) => path.clone(),
+            None => {
+                let stem = cut_path
+                    .file_stem()
+                    .map_or("cut".to_string(), |s| s.to_string_lossy().to_string());
+                cut_path.with_file_name(format!("{}_render.wav", stem))
+            }
+        }
+    }
+
+    /// Settings panel, returns true if any setting changed
+    pub fn ui_content(&mut self, ui: &mut Ui, cut_path: &Path) -> bool {
+        let old = self.clone();
+
+        ui.horizontal(|ui| {
+            ui.label("Tempo");
+            ui.add(
+                DragValue::new(&mut self.bpm)
+                    .range(20.0..=400.0)
+                    .speed(0.5)
+                    .suffix(" bpm"),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Time");
+            ui.add(DragValue::new(&mut self.beats_per_bar).range(1..=32));
+            ui.label("/");
+            ComboBox::from_id_source("beat_unit")
+                .width(40.0)
+                .selected_text(format!("{}", self.beat_unit))
+                .show_ui(ui, |ui| {
+                    for unit in [2, 4, 8, 16] {
+                        ui.selectable_value(&mut self.beat_unit, unit, format!("{}", unit));
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Rate");
+            ComboBox::from_id_source("sample_rate")
+                .selected_text(format!("{} Hz", self.sample_rate))
+                .show_ui(ui, |ui| {
+                    for rate in [44100, 48000, 88200, 96000] {
+                        ui.selectable_value(&mut self.sample_rate, rate, format!("{} Hz", rate));
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Format");
+            ComboBox::from_id_source("bit_depth")
+                .selected_text(self.bit_depth.name())
+                .show_ui(ui, |ui| {
+                    for bit_depth in BitDepth::ALL {
+                        ui.selectable_value(&mut self.bit_depth, bit_depth, bit_depth.name());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Quality");
+            ComboBox::from_id_source("quality")
+                .selected_text(self.quality.name())
+                .show_ui(ui, |ui| {
+                    for quality in Quality::ALL {
+                        ui.selectable_value(&mut self.quality, quality, quality.name());
+                    }
+                });
+        });
+
+        let output_path = self.output_path(cut_path);
+        ui.horizontal(|ui| {
+            if ui.button("...").clicked() {
+                let mut dialog = rfd::FileDialog::new().add_filter("wav", &["wav"]);
+                if let Some(directory) = output_path.parent() {
+                    dialog = dialog.set_directory(directory);
+                }
+                if let Some(file_name) = output_path.file_name() {
+                    dialog = dialog.set_file_name(file_name.to_string_lossy());
+                }
+                if let Some(path) = dialog.save_file() {
+                    self.path = Some(path);
+                }
+            }
+            if self.path.is_some() && ui.button("X").clicked() {
+                self.path = None;
+            }
+            ui.label(
+                output_path
+                    .file_name()
+                    .map_or("".to_string(), |s| s.to_string_lossy().to_string()),
+            );
+        });
+
+        *self != old
+    }
+}
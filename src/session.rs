@@ -0,0 +1,433 @@
+// session
+//
+// State across the cut lanes: the cut loaded in each slot, per lane mix
+// settings and the mixdown of all audible lanes into one stereo file,
+// optionally with a stem per lane. Saved as JSON, with cut paths relative
+// to the session file.
+use crate::{
+    cut_file,
+    model::Cut,
+    render::RenderSettings,
+    sinc::{self, CutRenderer},
+};
+use egui::{DragValue, Slider, Ui};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Number of cut lanes
+pub const NR_LANES: usize = 10;
+
+/// Extension of session files
+pub const SESSION_EXTENSION: &str = "session";
+
+/// Frames per block when mixing
+const BLOCK_FRAMES: usize = 4096;
+
+/// Mix settings of a lane
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaneMix {
+    /// Gain in dB
+    pub gain_db: f32,
+
+    /// Balance, -1 left .. 1 right
+    pub pan: f32,
+
+    pub mute: bool,
+
+    pub solo: bool,
+}
+
+impl LaneMix {
+    /// Left and right gain, panning attenuates the opposite side
+    pub fn gains(&self) -> (f32, f32) {
+        let gain = 10f32.powf(self.gain_db / 20.0);
+        let pan = self.pan.clamp(-1.0, 1.0);
+        (gain * (1.0 - pan).min(1.0), gain * (1.0 + pan).min(1.0))
+    }
+
+    /// Lane mix panel, returns true if any setting changed
+    pub fn ui_content(&mut self, ui: &mut Ui) -> bool {
+        let old = self.clone();
+
+        ui.horizontal(|ui| {
+            ui.label("Gain");
+            ui.add(
+                DragValue::new(&mut self.gain_db)
+                    .range(-60.0..=12.0)
+                    .speed(0.1)
+                    .suffix(" dB"),
+            );
+            ui.checkbox(&mut self.mute, "mute");
+            ui.checkbox(&mut self.solo, "solo");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Pan");
+            ui.add(Slider::new(&mut self.pan, -1.0..=1.0).show_value(false));
+            if ui.button("C").clicked() {
+                self.pan = 0.0;
+            }
+        });
+
+        *self != old
+    }
+}
+
+/// The cut in a slot
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Slot {
+    /// Path to the cut
+    pub cut_path: Option<PathBuf>,
+
+    /// Shown in the editor
+    pub enabled: bool,
+}
+
+/// Panel widths, None for the default
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    /// Settings panel on the left
+    pub settings_width: Option<f32>,
+
+    /// Wave panel on the right
+    pub wave_width: Option<f32>,
+}
+
+/// Session of all lanes
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Cut in each slot
+    pub slots: [Slot; NR_LANES],
+
+    /// Index of the selected slot
+    pub current: usize,
+
+    /// Panel layout
+    pub layout: Layout,
+
+    /// Mix settings per lane
+    pub lanes: [LaneMix; NR_LANES],
+
+    /// Mixdown settings, the lane length is used unless bars is set
+    pub render: RenderSettings,
+
+    /// Write a stem per lane next to the mixdown
+    pub stems: bool,
+}
+
+impl Session {
+    /// Mixdown settings panel, returns true if any setting changed
+    pub fn ui_content(&mut self, ui: &mut Ui, session_path: &Path) -> bool {
+        let old = self.clone();
+        // the cut render settings use the same widget ids
+        ui.push_id("session", |ui| self.render.ui_content(ui, session_path));
+        ui.checkbox(&mut self.stems, "stems, a file per lane");
+        *self != old
+    }
+
+    /// Load a session, cut paths are resolved against the session directory
+    pub fn load(path: &Path) -> Result<Session, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read session: {}", err))?;
+        let mut session: Session =
+            serde_json::from_str(&json).map_err(|err| format!("Malformed session: {}", err))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for slot in &mut session.slots {
+            if let Some(cut_path) = &slot.cut_path {
+                slot.cut_path = Some(cut_file::resolve_path(dir, cut_path));
+            }
+        }
+        session.current = session.current.min(NR_LANES - 1);
+        Ok(session)
+    }
+
+    /// Save the session, cut paths are stored relative to the session file when possible
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut session = self.clone();
+        if let Some(dir) = path.parent() {
+            for slot in &mut session.slots {
+                if let Some(relative) = slot
+                    .cut_path
+                    .as_ref()
+                    .and_then(|cut_path| cut_file::relative_path(dir, cut_path))
+                {
+                    slot.cut_path = Some(relative);
+                }
+            }
+        }
+        let json = serde_json::to_string_pretty(&session).map_err(|err| format!("{:?}", err))?;
+        std::fs::write(path, json).map_err(|err| format!("Could not write session: {}", err))
+    }
+
+    /// Lanes heard in the mixdown: enabled, not muted, and soloed if any lane is
+    pub fn audible(&self, enabled: &[bool; NR_LANES]) -> [bool; NR_LANES] {
+        let solo = self.lanes.iter().any(|lane| lane.solo);
+        std::array::from_fn(|i| {
+            let lane = &self.lanes[i];
+            enabled[i] && !lane.mute && (!solo || lane.solo)
+        })
+    }
+
+    /// Mixdown destination, `<session>_render.wav` unless set
+    pub fn output_path(&self, session_path: &Path) -> PathBuf {
+        self.render.output_path(session_path)
+    }
+
+    /// Stem destination of lane i, next to the mixdown
+    pub fn stem_path(&self, session_path: &Path, i: usize) -> PathBuf {
+        let path = self.output_path(session_path);
+        let stem = path
+            .file_stem()
+            .map_or("mixdown".to_string(), |s| s.to_string_lossy().to_string());
+        path.with_file_name(format!("{}_lane{}.wav", stem, i))
+    }
+
+    /// Render the audible lanes and mix them into one stereo file, shorter lanes
+    /// are padded with silence. Returns the paths written, mixdown first.
+    pub fn mixdown(
+        &self,
+        session_path: &Path,
+        cuts: [Option<&Cut>; NR_LANES],
+        enabled: &[bool; NR_LANES],
+    ) -> Result<Vec<PathBuf>, String> {
+        let audible = self.audible(enabled);
+
+        // lane settings, as the mixdown but with the lane length
+        let mut settings = self.render.clone();
+        settings.path = None;
+
+        let mut lanes = vec![];
+        for (i, cut) in cuts.iter().enumerate() {
+            if let (true, Some(cut)) = (audible[i], cut) {
+                let renderer = CutRenderer::new(*cut, &settings)
+                    .map_err(|err| format!("Lane #{}: {}", i, err))?;
+                lanes.push((i, renderer, self.lanes[i].gains()));
+            }
+        }
+        if lanes.is_empty() {
+            Err("No audible lanes to mix")?;
+        }
+        let len = lanes.iter().map(|(_, renderer, _)| renderer.len()).max();
+        let len = len.unwrap_or(0);
+        debug!("mixdown {} lanes, {} frames", lanes.len(), len);
+
+        let path = self.output_path(session_path);
+        let mut paths = vec![path.clone()];
+        let spec = self.render.bit_depth.wav_spec(self.render.sample_rate);
+        let error = |err: hound::Error| format!("{:?}", err);
+        let mut mix_writer = hound::WavWriter::create(&path, spec).map_err(error)?;
+        let mut stem_writers = vec![];
+        if self.stems {
+            for (i, _, _) in &lanes {
+                let path = self.stem_path(session_path, *i);
+                stem_writers.push(hound::WavWriter::create(&path, spec).map_err(error)?);
+                paths.push(path);
+            }
+        }
+
+        let mut mix = vec![0.0; 2 * BLOCK_FRAMES];
+        let mut block = vec![0.0; 2 * BLOCK_FRAMES];
+        let mut done = 0;
+        while done < len {
+            let frames = BLOCK_FRAMES.min(len - done);
+            let mix = &mut mix[..2 * frames];
+            mix.fill(0.0);
+            for (n, (_, renderer, (left, right))) in lanes.iter_mut().enumerate() {
+                let block = &mut block[..2 * frames];
+                renderer.process(block, frames);
+                for frame in block.chunks_exact_mut(2) {
+                    frame[0] *= *left;
+                    frame[1] *= *right;
+                }
+                if let Some(writer) = stem_writers.get_mut(n) {
+                    sinc::write_samples(writer, self.render.bit_depth, block).map_err(error)?;
+                }
+                for (m, s) in mix.iter_mut().zip(block.iter()) {
+                    *m += s;
+                }
+            }
+            sinc::write_samples(&mut mix_writer, self.render.bit_depth, mix).map_err(error)?;
+            done += frames;
+        }
+
+        mix_writer.finalize().map_err(error)?;
+        for writer in stem_writers {
+            writer.finalize().map_err(error)?;
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::sine_cut, model::Resize, render::BitDepth};
+
+    /// Empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cut_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn save_load() {
+        let dir = test_dir("session");
+        let path = dir.join("test.session");
+
+        let mut session = Session::default();
+        session.slots[0] = Slot {
+            cut_path: Some(dir.join("cuts").join("a.cut")),
+            enabled: true,
+        };
+        session.slots[3] = Slot {
+            cut_path: Some(dir.join("b.cut")),
+            enabled: false,
+        };
+        session.current = 3;
+        session.layout = Layout {
+            settings_width: Some(250.0),
+            wave_width: None,
+        };
+        session.lanes[3] = LaneMix {
+            gain_db: -6.0,
+            pan: 0.5,
+            mute: true,
+            solo: false,
+        };
+        session.lanes[7].solo = true;
+        session.stems = true;
+        session.save(&path).unwrap();
+
+        // cut paths relative to the session file
+        let json = std::fs::read_to_string(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let stored =
+            |i: usize| serde_json::from_value::<PathBuf>(json["slots"][i]["cut_path"].clone());
+        assert_eq!(stored(0).unwrap(), Path::new("cuts").join("a.cut"));
+        assert_eq!(stored(3).unwrap(), Path::new("b.cut"));
+        assert!(json["slots"][1]["cut_path"].is_null());
+
+        assert_eq!(Session::load(&path).unwrap(), session);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn audible() {
+        let mut session = Session::default();
+        let mut enabled = [true; NR_LANES];
+        enabled[1] = false;
+        session.lanes[2].mute = true;
+        assert_eq!(session.audible(&enabled)[..4], [true, false, false, true]);
+
+        // only soloed lanes are heard, unless muted or disabled
+        session.lanes[1].solo = true;
+        session.lanes[2].solo = true;
+        session.lanes[3].solo = true;
+        assert_eq!(session.audible(&enabled)[..4], [false, false, false, true]);
+        assert!(session.audible(&enabled)[4..].iter().all(|a| !a));
+    }
+
+    #[test]
+    fn gains() {
+        let gains = |gain_db: f32, pan: f32| {
+            LaneMix {
+                gain_db,
+                pan,
+                ..LaneMix::default()
+            }
+            .gains()
+        };
+        assert_eq!(gains(0.0, 0.0), (1.0, 1.0));
+        assert_eq!(gains(0.0, -1.0), (1.0, 0.0));
+        assert_eq!(gains(0.0, 0.5), (0.5, 1.0));
+        assert_eq!(gains(0.0, 2.0), (0.0, 1.0));
+
+        let (left, right) = gains(-20.0, -0.5);
+        assert!((left - 0.1).abs() < 1e-6);
+        assert!((right - 0.05).abs() < 1e-6);
+        let (left, _) = gains(6.0, 0.0);
+        assert!((left - 1.995).abs() < 1e-3);
+    }
+
+    #[test]
+    fn mixdown() {
+        let dir = test_dir("mixdown");
+        let session_path = dir.join("test.session");
+
+        // lanes of one bar and half a bar, a muted and a disabled lane
+        let (mut long, _) = sine_cut(1.0);
+        long.set_bars(1.0, Resize::ExtendTrim);
+        let (mut short, _) = sine_cut(1.0);
+        short.set_bars(0.5, Resize::ExtendTrim);
+        let mut cuts = [None; NR_LANES];
+        cuts[0] = Some(&long);
+        cuts[1] = Some(&long);
+        cuts[2] = Some(&short);
+        cuts[3] = Some(&short);
+        let mut enabled = [false; NR_LANES];
+        enabled[..3].fill(true);
+
+        let mut session = Session::default();
+        session.render.sample_rate = 8000;
+        session.render.bit_depth = BitDepth::Float32;
+        session.stems = true;
+        session.lanes[0].pan = -0.5;
+        session.lanes[1].mute = true;
+        session.lanes[2].gain_db = -6.0;
+
+        let paths = session.mixdown(&session_path, cuts, &enabled).unwrap();
+        assert_eq!(
+            paths,
+            [
+                dir.join("test_render.wav"),
+                dir.join("test_render_lane0.wav"),
+                dir.join("test_render_lane2.wav"),
+            ]
+        );
+        let read = |path: &Path| -> Vec<f32> {
+            let reader = hound::WavReader::open(path).unwrap();
+            reader.into_samples().map(Result::unwrap).collect()
+        };
+        let mix = read(&paths[0]);
+        let stems = [read(&paths[1]), read(&paths[2])];
+
+        // each stem is its lane rendered alone with the lane gains, the
+        // shorter lane padded with silence
+        let len = CutRenderer::new(&long, &session.render).unwrap().len();
+        assert_eq!(mix.len(), 2 * len);
+        for (stem, (i, cut)) in stems.iter().zip([(0, &long), (2, &short)]) {
+            let mut renderer = CutRenderer::new(cut, &session.render).unwrap();
+            let mut lane = vec![0.0; 2 * len];
+            renderer.process(&mut lane, len);
+            let (left, right) = session.lanes[i].gains();
+            for frame in lane.chunks_exact_mut(2) {
+                frame[0] *= left;
+                frame[1] *= right;
+            }
+            assert_eq!(*stem, lane);
+        }
+        let short_len = CutRenderer::new(&short, &session.render).unwrap().len();
+        assert!(short_len < len);
+        assert!(stems[1][2 * short_len..].iter().all(|s| *s == 0.0));
+        assert!(stems[1][..2 * short_len].iter().any(|s| *s != 0.0));
+
+        // the mix is the sum of the stems
+        for (n, m) in mix.iter().enumerate() {
+            assert_eq!(*m, 0.0 + stems[0][n] + stems[1][n]);
+        }
+
+        // nothing to mix
+        session.lanes[0].mute = true;
+        session.lanes[2].mute = true;
+        assert!(session.mixdown(&session_path, cuts, &enabled).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
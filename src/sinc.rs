@@ -1,11 +1,15 @@
 // sinc interpolation
-use crate::cut_panel::Cut;
-// use crate::wav_panel::Wav;
+use crate::{model::Cut, render::BitDepth, render::RenderSettings};
 // use wav::Header;
 // use hound::WavSpec;
 use log::debug;
+use serde::{Deserialize, Serialize};
 
-use std::f32::consts::PI;
+use std::{
+    f32::consts::PI,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
 
 #[inline(always)]
 fn sinc(x: f32) -> f32 {
@@ -16,72 +20,504 @@ fn sinc(x: f32) -> f32 {
     }
 }
 
-pub fn sinc_resample(cut: &Cut) {
-    let bpm = 120.0; // in 4/4
-    let wav_spec = cut.wav_data.get_wav_spec().unwrap();
-    debug!("wav_spec {:?}", wav_spec);
-
-    let _in_rate = wav_spec.sample_rate as f32;
-    let out_rate = 48000.0;
+/// Resampling quality
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Quality {
+    Draft,
+    #[default]
+    Normal,
+    High,
+    Best,
+}
 
-    let bars = cut.get_bars();
-    debug!("bars {}", bars);
+impl Quality {
+    pub const ALL: [Quality; 4] = [
+        Quality::Draft,
+        Quality::Normal,
+        Quality::High,
+        Quality::Best,
+    ];
 
-    let out_time_s = bars * 4.0 * 60.0 / bpm;
-    debug!("out_time {} seconds", out_time_s);
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quality::Draft => "draft",
+            Quality::Normal => "normal",
+            Quality::High => "high",
+            Quality::Best => "best",
+        }
+    }
 
-    let out_samples = out_rate * out_time_s;
-    debug!("out_samples {}", out_samples);
+    /// Kernel half width, in input samples at unity rate
+    fn half_width(&self) -> f32 {
+        match self {
+            Quality::Draft => 4.0,
+            Quality::Normal => 8.0,
+            Quality::High => 16.0,
+            Quality::Best => 32.0,
+        }
+    }
 
-    let in_len = cut.wav.get_data_len();
-    debug!("in_len {}", in_len);
-    let in_out = in_len as f32 / out_samples;
-    debug!("in_out {}", in_out);
+    /// Largest kernel stretch, bounds the cost of very fast moves
+    fn max_stretch(&self) -> f32 {
+        match self {
+            Quality::Draft => 4.0,
+            Quality::Normal => 8.0,
+            Quality::High => 16.0,
+            Quality::Best => 32.0,
+        }
+    }
+}
 
-    let nr_sinc_samples = 10;
-    let first_sinc_sample = nr_sinc_samples / 2;
+/// Variable ratio resampler, see `octave/borg.m`.
+///
+/// Each output sample is a Hann windowed sinc over the input. When the input
+/// is read faster than one sample per output sample, the kernel is stretched
+/// by the rate so the cutoff follows the output Nyquist frequency.
+#[derive(Copy, Clone, Debug)]
+pub struct Resampler {
+    half_width: f32,
+    max_stretch: f32,
+}
 
-    let _offset = cut.wav.get_data_offset();
+/// Cutoff relative to Nyquist, rolls off slightly early to suppress folding
+const CUTOFF: f32 = 0.9;
 
-    let mut out = vec![];
-    for i in 0..out_samples as usize {
-        // time in bars
-        let t_bars = bars * i as f32 / out_samples;
-        // recreate sample at time t
-        let t_0_1 = cut.sample_spline(t_bars).unwrap();
+impl Resampler {
+    pub fn new(quality: Quality) -> Self {
+        Resampler {
+            half_width: quality.half_width(),
+            max_stretch: quality.max_stretch(),
+        }
+    }
 
-        let t = t_0_1 * in_len as f32;
+    /// Interpolate (left, right) at input position `pos`.
+    ///
+    /// `rate` is the local speed in input samples per output sample,
+    /// its sign is ignored. `get` returns the input sample at an index.
+    pub fn sample<F>(&self, pos: f64, rate: f64, get: F) -> (f32, f32)
+    where
+        F: Fn(isize) -> (f32, f32),
+    {
+        let stretch = (rate.abs() as f32).clamp(1.0, self.max_stretch);
+        let width = self.half_width * stretch;
 
-        let min_t = t.floor(); // the sample left of the one to re-create
-        let diff = t - min_t;
+        let first = (pos - width as f64).ceil() as isize;
+        let last = (pos + width as f64).floor() as isize;
 
         let mut left = 0.0;
         let mut right = 0.0;
-        for j in 0..=nr_sinc_samples {
-            let sample_position = j + min_t as usize - first_sinc_sample;
-            let (in_sample_left, in_sample_right) =
-                cut.wav.get_sample(sample_position, &cut.wav_data);
+        let mut norm = 0.0;
+        for k in first..=last {
+            // distance to the output sample, in input samples
+            let x = (k as f64 - pos) as f32;
+            let w = sinc(CUTOFF * x / stretch) * (1.0 + (PI * x / width).cos());
+
+            let (l, r) = get(k);
+            left += w * l;
+            right += w * r;
+            norm += w;
+        }
+
+        if norm != 0.0 {
+            (left / norm, right / norm)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+/// Length of the anti-click ramp applied at hard fader transitions, in seconds
+const FADER_RAMP_S: f32 = 0.002;
+
+/// Fader gain with a limited slew rate.
+///
+/// A hard 0 <-> 1 jump in the fader lane is turned into a short linear ramp
+/// of `FADER_RAMP_S`, while slower fades pass through unchanged.
+struct FaderRamp {
+    gain: f32,
+    max_step: f32,
+}
+
+impl FaderRamp {
+    fn new(gain: f32, out_rate: f32) -> Self {
+        FaderRamp {
+            gain,
+            max_step: 1.0 / (FADER_RAMP_S * out_rate),
+        }
+    }
+
+    /// Step towards target. The gain reached is monotone in the gain before,
+    /// see `CutRenderer::fader_at`.
+    fn next(&mut self, target: f32) -> f32 {
+        self.gain = target
+            .max(self.gain - self.max_step)
+            .min(self.gain + self.max_step);
+        self.gain
+    }
+}
+
+/// Frames per block when rendering to file
+const BLOCK_FRAMES: usize = 4096;
+
+/// Longest render, in seconds
+const MAX_SECONDS: f32 = 24.0 * 60.0 * 60.0;
+
+/// Streaming renderer of a cut.
+///
+/// Renders interleaved stereo frames block by block, advancing a clock of
+/// output frames. Any sequence of block sizes and seeks gives the same samples
+/// as rendering the whole cut at once. The cut is borrowed, or owned as an
+/// `Arc<Cut>` snapshot when rendering on another thread.
+pub struct CutRenderer<C: Deref<Target = Cut>> {
+    cut: C,
+    resampler: Resampler,
+    out_rate: f32,
+    /// length of the render in bars
+    bars: f32,
+    /// length of the render in frames
+    len: usize,
+    /// window length in input samples
+    in_len: usize,
+    /// next frame
+    frame: usize,
+    /// spline value at frame, unwrapped
+    value: f32,
+    fader: FaderRamp,
+}
+
+impl<C: Deref<Target = Cut>> CutRenderer<C> {
+    pub fn new(cut: C, settings: &RenderSettings) -> Result<Self, String> {
+        if cut.wav_data.len == 0 {
+            Err("No sample loaded")?;
+        }
+        settings.validate()?;
+        // the window is in samples at the rate of the data, so the input rate
+        // only sets how many samples a bar spans, not the pitch
+        debug!(
+            "in rate {}, file rate {:?}",
+            cut.wav_data.get_rate(),
+            cut.wav_data.get_source_rate()
+        );
+        let out_rate = settings.sample_rate as f32;
+
+        let bars = settings.bars.unwrap_or(cut.get_bars());
+        debug!("bars {}", bars);
+
+        let out_time_s = bars * settings.bar_seconds();
+        debug!("out_time {} seconds", out_time_s);
+        if !(0.0..=MAX_SECONDS).contains(&out_time_s) {
+            Err(format!("Render of {} seconds too long", out_time_s))?;
+        }
+
+        let len = (out_rate * out_time_s) as usize;
+        debug!("out_samples {}", len);
+
+        let in_len = cut.wav.get_data_len();
+        debug!("in_len {}", in_len);
+
+        let mut renderer = CutRenderer {
+            cut,
+            resampler: Resampler::new(settings.quality),
+            out_rate,
+            bars,
+            len,
+            in_len,
+            frame: 0,
+            value: 0.0,
+            fader: FaderRamp::new(0.0, out_rate),
+        };
+        renderer.reset();
+        Ok(renderer)
+    }
+
+    /// Length in frames
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Next frame to render
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Position of the clock, in bars
+    pub fn bar_position(&self) -> f32 {
+        self.bars_at(self.frame)
+    }
 
-            let sinc_sample = sinc((j as f32 - first_sinc_sample as f32) - diff);
+    /// All frames rendered
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.len
+    }
+
+    /// Restart from the first frame
+    pub fn reset(&mut self) {
+        self.seek_frame(0);
+    }
+
+    /// Continue from the frame closest to bar, as if rendered from the start
+    pub fn seek(&mut self, bar: f32) {
+        let frame = if self.bars > 0.0 {
+            (bar / self.bars * self.len as f32).round().max(0.0) as usize
+        } else {
+            0
+        };
+        self.seek_frame(frame.min(self.len));
+    }
+
+    /// Continue where other is, at its bar with its fader gain, so swapping
+    /// the cut while playing does not click
+    pub fn continue_from<D: Deref<Target = Cut>>(&mut self, other: &CutRenderer<D>) {
+        self.seek(other.bar_position());
+        self.fader.gain = other.fader.gain;
+    }
+
+    fn seek_frame(&mut self, frame: usize) {
+        self.frame = frame;
+        self.value = self.value_at(frame);
+        self.fader = self.fader_at(frame);
+    }
+
+    /// Fader ramp before frame, as rendered from the first frame.
+    ///
+    /// The gains lie in 0..=1 and a step is monotone in the gain, so ramps
+    /// started closed and open a window before frame enclose the rendered
+    /// ramp. Once they meet at frame they equal it. A ramp that is still
+    /// moving doubles the window, up to the first frame.
+    fn fader_at(&self, frame: usize) -> FaderRamp {
+        let target = |frame: usize| self.cut.sample_gain(self.bars_at(frame)).unwrap_or(0.0);
+        let mut window = 2 * (FADER_RAMP_S * self.out_rate).ceil() as usize + 2;
+        loop {
+            let start = frame.saturating_sub(window);
+            let (closed, open) = if start == 0 {
+                (target(0), target(0))
+            } else {
+                (0.0, 1.0)
+            };
+            let mut closed = FaderRamp::new(closed, self.out_rate);
+            let mut open = FaderRamp::new(open, self.out_rate);
+            for frame in start..frame {
+                let target = target(frame);
+                closed.next(target);
+                open.next(target);
+            }
+            if closed.gain == open.gain || start == 0 {
+                return closed;
+            }
+            window *= 2;
+        }
+    }
+
+    /// time in bars of frame
+    fn bars_at(&self, frame: usize) -> f32 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        self.bars * frame as f32 / self.len as f32
+    }
+
+    /// spline value at frame, unwrapped
+    fn value_at(&self, frame: usize) -> f32 {
+        self.cut.sample_spline(self.bars_at(frame)).unwrap_or(0.0)
+    }
+
+    /// Render up to `frames` interleaved stereo frames into out, returns the
+    /// number of frames rendered. Frames past the end are silent.
+    pub fn process(&mut self, out: &mut [f32], frames: usize) -> usize {
+        let frames = frames.min(out.len() / 2);
+        let rendered = frames.min(self.len - self.frame);
+
+        let cut = &*self.cut;
+        let warping = cut.is_warping();
+        let get = |k: isize| cut.wav.get_sample(k, &cut.wav_data, warping);
+
+        for out in out[..2 * rendered].chunks_exact_mut(2) {
+            // fader gain at time t
+            let gain = self
+                .fader
+                .next(cut.sample_gain(self.bars_at(self.frame)).unwrap_or(0.0));
+
+            // input position in the window, wrapped or clamped as in the cut panel
+            let pos = cut.wrap_value(self.value) as f64 * self.in_len as f64;
+            // local rate from the next (unwrapped) value
+            let next = self.value_at(self.frame + 1);
+            let rate = (next - self.value) as f64 * self.in_len as f64;
+            let (left, right) = self.resampler.sample(pos, rate, get);
+            self.value = next;
+            self.frame += 1;
+
+            out[0] = left * gain;
+            out[1] = right * gain;
+        }
+        out[2 * rendered..2 * frames].fill(0.0);
+        rendered
+    }
+}
+
+/// Render the cut according to settings, returns the path written
+pub fn sinc_resample(cut: &Cut, settings: &RenderSettings) -> Result<PathBuf, String> {
+    let mut renderer = CutRenderer::new(cut, settings)?;
+    let path = settings.output_path(&cut.cut_path);
+    debug!("render to {:?}", path);
+
+    write_renderer(&path, settings, &mut renderer).map_err(|err| format!("{:?}", err))?;
+    Ok(path)
+}
+
+/// Write the rest of the render block by block, in the format given by settings
+fn write_renderer(
+    path: &Path,
+    settings: &RenderSettings,
+    renderer: &mut CutRenderer<&Cut>,
+) -> Result<(), hound::Error> {
+    let spec = settings.bit_depth.wav_spec(settings.sample_rate);
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    let mut block = vec![0.0; 2 * BLOCK_FRAMES];
+    while !renderer.is_finished() {
+        let frames = renderer.process(&mut block, BLOCK_FRAMES);
+        write_samples(&mut writer, settings.bit_depth, &block[..2 * frames])?;
+    }
+    writer.finalize()
+}
+
+/// Render the cut according to settings, interleaved stereo samples
+pub fn render(cut: &Cut, settings: &RenderSettings) -> Result<Vec<f32>, String> {
+    let mut renderer = CutRenderer::new(cut, settings)?;
+    let mut out = vec![0.0; 2 * renderer.len()];
+    renderer.process(&mut out, renderer.len());
+    Ok(out)
+}
+
+/// Write interleaved stereo samples in the format given by settings
+pub fn write_wav(
+    path: &Path,
+    settings: &RenderSettings,
+    samples: &[f32],
+) -> Result<(), hound::Error> {
+    let spec = settings.bit_depth.wav_spec(settings.sample_rate);
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    write_samples(&mut writer, settings.bit_depth, samples)?;
+    writer.finalize()
+}
+
+/// Write samples in the format given by bit_depth
+pub(crate) fn write_samples<W: std::io::Write + std::io::Seek>(
+    writer: &mut hound::WavWriter<W>,
+    bit_depth: BitDepth,
+    samples: &[f32],
+) -> Result<(), hound::Error> {
+    match bit_depth {
+        BitDepth::Int16 => {
+            for s in samples {
+                writer.write_sample((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+            }
+        }
+        BitDepth::Int24 => {
+            for s in samples {
+                writer.write_sample((s.clamp(-1.0, 1.0) * 8_388_607.0) as i32)?;
+            }
+        }
+        BitDepth::Float32 => {
+            for s in samples {
+                writer.write_sample(*s)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::sine_cut, model::Knot};
+    use egui::pos2;
 
-            left += sinc_sample * in_sample_left;
-            right += sinc_sample * in_sample_right;
+    #[test]
+    fn block_sizes() {
+        let (cut, settings) = sine_cut(0.1);
+        let expected = render(&cut, &settings).unwrap();
+
+        let mut renderer = CutRenderer::new(&cut, &settings).unwrap();
+        let mut out = vec![];
+        let mut block = vec![0.0; 2 * 4096];
+        for frames in [1, 7, 64, 333, 4096, 5].into_iter().cycle() {
+            if renderer.is_finished() {
+                break;
+            }
+            let rendered = renderer.process(&mut block, frames);
+            out.extend_from_slice(&block[..2 * rendered]);
+        }
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn seek() {
+        let (cut, settings) = sine_cut(0.1);
+        let expected = render(&cut, &settings).unwrap();
+
+        let mut renderer = CutRenderer::new(&cut, &settings).unwrap();
+        let mut block = vec![0.0; 2 * 1000];
+        // forward, backward and to the start
+        for bar in [0.05, 0.025, 0.0] {
+            renderer.seek(bar);
+            let frame = renderer.frame();
+            assert_eq!(frame, (bar / 0.1 * renderer.len() as f32).round() as usize);
+            assert_eq!(renderer.bar_position(), bar);
+            let rendered = renderer.process(&mut block, 1000);
+            assert_eq!(
+                block[..2 * rendered],
+                expected[2 * frame..2 * (frame + rendered)]
+            );
         }
 
-        out.push(left);
-        out.push(right);
+        // past the end
+        renderer.seek(1.0);
+        assert!(renderer.is_finished());
+        assert_eq!(renderer.process(&mut block, 1000), 0);
+        assert!(block.iter().all(|s| *s == 0.0));
     }
 
-    let spec = hound::WavSpec {
-        channels: 2,
-        sample_rate: 48000,
-        bits_per_sample: 32,
-        sample_format: hound::SampleFormat::Float,
-    };
+    #[test]
+    fn seek_fader_ramp() {
+        let (mut cut, settings) = sine_cut(0.1);
+        let end = cut.get_bars();
+        // a hard cut closing the fader, and a fade faster than the ramp
+        let knots = [
+            (0.0, 1.0),
+            (0.05, 1.0),
+            (0.05, 0.0),
+            (0.07, 0.0),
+            (0.0701, 1.0),
+        ];
+        let mut knots: Vec<Knot> = knots.iter().map(|(x, y)| Knot::new(pos2(*x, *y))).collect();
+        knots.push(Knot::new(pos2(end, 1.0)));
+        cut.set_fader_knots(knots);
+        let expected = render(&cut, &settings).unwrap();
 
-    let mut writer = hound::WavWriter::create("./audio/re_sample.wav", spec).unwrap();
-    for s in out.into_iter() {
-        let _ = writer.write_sample(s);
+        let mut renderer = CutRenderer::new(&cut, &settings).unwrap();
+        let ramp = (FADER_RAMP_S * settings.sample_rate as f32) as usize;
+        let len = renderer.len() as f32;
+        let frame = |bar: f32| (bar / 0.1 * len) as usize;
+        let mut block = vec![0.0; 2 * 1000];
+        for start in [
+            frame(0.05) + ramp / 2,
+            frame(0.0701) + ramp / 4,
+            frame(0.05) - 1,
+        ] {
+            renderer.seek_frame(start);
+            let rendered = renderer.process(&mut block, 1000);
+            assert_eq!(
+                block[..2 * rendered],
+                expected[2 * start..2 * (start + rendered)]
+            );
+        }
+        // halfway down the ramp, where the fader is closed
+        let start = frame(0.05) + ramp / 2;
+        assert_eq!(cut.sample_gain(renderer.bars_at(start)), Some(0.0));
+        assert!(expected[2 * start..2 * start + 8].iter().any(|s| *s != 0.0));
     }
-    writer.finalize().unwrap();
 }
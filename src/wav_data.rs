@@ -0,0 +1,513 @@
+// wav data
+use serde::{Deserialize, Serialize};
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+// use wav::{BitDepth, Header};
+use hound::{SampleFormat, WavReader, WavSpec};
+use log::{debug, trace};
+use rubato::{FftFixedIn, Resampler};
+use symphonia::core::{
+    audio::SampleBuffer, errors::Error, formats::FormatOptions, io::MediaSourceStream, probe::Hint,
+};
+
+/// Extensions of the sample formats that can be loaded
+pub const SAMPLE_EXTENSIONS: [&str; 4] = ["wav", "mp3", "flac", "ogg"];
+
+#[derive(Clone, Default, Debug)]
+pub struct WavData {
+    /// spec of the file, with the original sample rate
+    wav_spec: Option<WavSpec>,
+    /// sample rate of the samples, after conversion
+    rate: u32,
+    /// samples, one vector per channel, shared by clones
+    channels: Arc<Vec<Vec<f32>>>,
+    /// the max length
+    pub len: usize,
+    /// filename
+    pub filename: String,
+}
+
+impl WavData {
+    /// load, mono files are kept mono, all channels of multichannel files are kept.
+    /// Integer (8/16/24/32 bit) and float (32/64 bit) samples are normalized to -1..1.
+    /// MP3, FLAC and Ogg Vorbis files are decoded.
+    pub fn load_wav_data(path: PathBuf) -> Result<Self, String> {
+        let filename = path
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+        let ext = path
+            .extension()
+            .map_or(String::new(), |ext| ext.to_string_lossy().to_lowercase());
+        let (wav_spec, samples) = match ext.as_str() {
+            "wav" => match hound::WavReader::open(&path) {
+                Ok(reader) => read_samples(reader)?,
+                // hound does not read 64 bit float
+                Err(err) => read_float64(&path)?.ok_or_else(|| wav_error(err))?,
+            },
+            _ => read_compressed(&path)?,
+        };
+        trace!("wav_spec {:?}", wav_spec);
+        let nr_channels = wav_spec.channels as usize;
+        if nr_channels == 0 {
+            Err("No channels in file")?;
+        }
+
+        let len = samples.len() / nr_channels;
+        trace!("duration: len samples {}", len);
+
+        // de-interleave
+        let mut channels = vec![Vec::with_capacity(len); nr_channels];
+        for (i, s) in samples.into_iter().enumerate() {
+            channels[i % nr_channels].push(s);
+        }
+        // drop a trailing incomplete frame
+        for channel in &mut channels {
+            channel.truncate(len);
+        }
+
+        Ok(WavData {
+            wav_spec: Some(wav_spec),
+            rate: wav_spec.sample_rate,
+            channels: Arc::new(channels),
+            len,
+            filename,
+        })
+    }
+
+    pub fn get_wav_spec(&self) -> Option<WavSpec> {
+        self.wav_spec
+    }
+
+    /// Sample rate of the samples
+    pub fn get_rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Sample rate of the file
+    pub fn get_source_rate(&self) -> Option<u32> {
+        self.wav_spec.map(|wav_spec| wav_spec.sample_rate)
+    }
+
+    /// Convert the samples to rate
+    pub fn convert_rate(&mut self, rate: u32) -> Result<(), String> {
+        if rate == self.rate || self.len == 0 {
+            return Ok(());
+        }
+        debug!(
+            "convert {} from {} to {} Hz",
+            self.filename, self.rate, rate
+        );
+        self.channels = Arc::new(resample(&self.channels, self.rate, rate)?);
+        self.len = self.channels[0].len();
+        self.rate = rate;
+        Ok(())
+    }
+
+    /// Number of channels in the file
+    pub fn nr_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Mono file, left and right are the same
+    pub fn is_mono(&self) -> bool {
+        self.channels.len() == 1
+    }
+
+    /// Sample at index of the channel pair used as left and right,
+    /// channels not in the file fall back to the last channel
+    pub fn get_sample(&self, index: usize, (left, right): (usize, usize)) -> (f32, f32) {
+        let get = |channel: usize| {
+            self.channels
+                .get(channel.min(self.channels.len().saturating_sub(1)))
+                .and_then(|samples| samples.get(index))
+                .copied()
+                .unwrap_or(0.0)
+        };
+        (get(left), get(right))
+    }
+}
+
+/// Resample channels of equal length, the output is aligned with the input
+fn resample(channels: &[Vec<f32>], from: u32, to: u32) -> Result<Vec<Vec<f32>>, String> {
+    const CHUNK: usize = 1024;
+    let mut resampler =
+        FftFixedIn::<f32>::new(from as usize, to as usize, CHUNK, 2, channels.len())
+            .map_err(|err| format!("Could not convert sample rate: {}", err))?;
+
+    let len = channels[0].len();
+    let out_len = (len as f64 * to as f64 / from as f64).round() as usize;
+    // the resampler delays its output, run until the delayed end is out
+    let delay = resampler.output_delay();
+    let mut out = vec![Vec::with_capacity(out_len + delay); channels.len()];
+    let mut pos = 0;
+    while out[0].len() < out_len + delay {
+        let chunk: Vec<&[f32]> = channels
+            .iter()
+            .map(|channel| &channel[pos.min(len)..(pos + CHUNK).min(len)])
+            .collect();
+        let processed = if pos + CHUNK <= len {
+            resampler.process(&chunk, None)
+        } else if pos < len {
+            // zero padded
+            resampler.process_partial(Some(&chunk), None)
+        } else {
+            // flush
+            resampler.process_partial(None::<&[&[f32]]>, None)
+        }
+        .map_err(|err| format!("Could not convert sample rate: {}", err))?;
+        pos += CHUNK;
+        for (out, processed) in out.iter_mut().zip(processed) {
+            out.extend(processed);
+        }
+    }
+
+    for out in &mut out {
+        out.drain(..delay);
+        out.truncate(out_len);
+    }
+    Ok(out)
+}
+
+/// Decode a compressed file (MP3, FLAC, Ogg Vorbis), interleaved samples
+fn read_compressed(path: &Path) -> Result<(WavSpec, Vec<f32>), String> {
+    let file = std::fs::File::open(path).map_err(|err| format!("Could not read file: {}", err))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    // gapless, trims encoder delay and padding
+    let format_options = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, stream, &format_options, &Default::default())
+        .map_err(decode_error)?
+        .format;
+    let track = format.default_track().ok_or("No audio track in file")?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &Default::default())
+        .map_err(decode_error)?;
+
+    let mut spec = None;
+    let mut samples = vec![];
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // end of stream
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => Err(decode_error(err))?,
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(buffer) => {
+                spec = Some(*buffer.spec());
+                let mut interleaved =
+                    SampleBuffer::<f32>::new(buffer.capacity() as u64, *buffer.spec());
+                interleaved.copy_interleaved_ref(buffer);
+                samples.extend_from_slice(interleaved.samples());
+            }
+            // corrupt packet, skip it
+            Err(Error::DecodeError(msg)) => debug!("decode error {}", msg),
+            Err(err) => Err(decode_error(err))?,
+        }
+    }
+
+    let spec = spec.ok_or("No audio decoded")?;
+    let wav_spec = WavSpec {
+        channels: spec.channels.count() as u16,
+        sample_rate: spec.rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    Ok((wav_spec, samples))
+}
+
+/// Describe a symphonia error for the status bar
+fn decode_error(err: Error) -> String {
+    match err {
+        Error::IoError(err) => format!("Could not read file: {}", err),
+        Error::Unsupported(what) => format!("Unsupported format: {}", what),
+        err => format!("Could not decode: {}", err),
+    }
+}
+
+/// Describe a hound error for the status bar
+fn wav_error(err: hound::Error) -> String {
+    match err {
+        hound::Error::IoError(err) => format!("Could not read file: {}", err),
+        hound::Error::FormatError(msg) => format!("Malformed WAV: {}", msg),
+        hound::Error::Unsupported => "Unsupported WAV format".to_string(),
+        err => format!("Could not read WAV: {}", err),
+    }
+}
+
+/// Interleaved samples normalized to -1..1
+fn read_samples<R: Read>(mut reader: WavReader<R>) -> Result<(WavSpec, Vec<f32>), String> {
+    let wav_spec = reader.spec();
+    let samples = match (wav_spec.sample_format, wav_spec.bits_per_sample) {
+        (SampleFormat::Float, 32) => reader.samples::<f32>().collect::<Result<_, _>>(),
+        (SampleFormat::Int, bits @ 1..=32) => {
+            // 8 bit (unsigned in the file) is offset to signed by hound
+            let scale = 1.0 / (1u64 << (bits - 1)) as f64;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| (s as f64 * scale) as f32))
+                .collect::<Result<_, _>>()
+        }
+        (format, bits) => Err(format!(
+            "Unsupported sample format {:?} {} bit",
+            format, bits
+        ))?,
+    };
+    Ok((wav_spec, samples.map_err(wav_error)?))
+}
+
+/// Read a 64 bit float WAV, interleaved samples. None if not a 64 bit float WAV
+fn read_float64(path: &Path) -> Result<Option<(WavSpec, Vec<f32>)>, String> {
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+    const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+    let bytes = std::fs::read(path).map_err(|err| format!("Could not read file: {}", err))?;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Ok(None);
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at =
+        |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+    let mut fmt = None;
+    let mut data = None;
+    let mut i = 12;
+    while i + 8 <= bytes.len() {
+        let id = &bytes[i..i + 4];
+        let len = u32_at(i + 4) as usize;
+        let body = i + 8..(i + 8 + len).min(bytes.len());
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let mut tag = u16_at(body.start);
+                // sub format is the first two bytes of the GUID
+                if tag == WAVE_FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    tag = u16_at(body.start + 24);
+                }
+                fmt = Some((
+                    tag,
+                    u16_at(body.start + 2),
+                    u32_at(body.start + 4),
+                    u16_at(body.start + 14),
+                ));
+            }
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // chunks are padded to even length
+        i += 8 + len + (len & 1);
+    }
+
+    let Some((WAVE_FORMAT_IEEE_FLOAT, channels, sample_rate, 64)) = fmt else {
+        return Ok(None);
+    };
+    let data = data.ok_or("Malformed WAV: no data chunk")?;
+    let samples = bytes[data]
+        .chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+        .collect();
+
+    let wav_spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 64,
+        sample_format: SampleFormat::Float,
+    };
+    Ok(Some((wav_spec, samples)))
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct CheckPointData {
+    offset: usize, // in samples, wrapping
+    len: usize,    // in samples
+    /// channels used as left and right, None for the first two
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    channels: Option<(usize, usize)>,
+}
+
+impl CheckPointData {
+    /// Scale offset and len to another sample rate
+    fn scale(&mut self, ratio: f64) {
+        self.offset = (self.offset as f64 * ratio).round() as usize;
+        self.len = (self.len as f64 * ratio).round() as usize;
+    }
+}
+
+/// The window (offset, len) of the sample used by a cut
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Wav {
+    data: CheckPointData,
+
+    /// sample rate of offset and len, None for the rate of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate: Option<u32>,
+
+    #[serde(skip)]
+    undo: Vec<CheckPointData>,
+    redo: Vec<CheckPointData>,
+
+    /// Bumped on every change of the window
+    #[serde(skip)]
+    revision: u64,
+}
+
+impl Wav {
+    /// get_sample at p, where p = 0..data.len is inside the window.
+    /// Outside the window p wraps around the window if warping, else it
+    /// continues into the sample. The window itself wraps around the sample end.
+    pub fn get_sample(&self, p: isize, wav_data: &WavData, warping: bool) -> (f32, f32) {
+        if wav_data.len == 0 {
+            return (0.0, 0.0);
+        }
+        let p = if warping && self.data.len > 0 {
+            p.rem_euclid(self.data.len as isize)
+        } else {
+            p
+        };
+        let t = (p + self.data.offset as isize).rem_euclid(wav_data.len as isize);
+        wav_data.get_sample(t as usize, self.get_channels())
+    }
+
+    /// Set the sample rate of offset and len, offset and len are scaled
+    /// from the previous rate, or taken as is if there was none
+    pub fn set_rate(&mut self, rate: u32) {
+        if let Some(previous) = self.rate.filter(|previous| *previous != rate) {
+            let ratio = rate as f64 / previous as f64;
+            self.data.scale(ratio);
+            for data in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+                data.scale(ratio);
+            }
+        }
+        self.rate = Some(rate);
+        self.revision += 1;
+    }
+
+    /// Get channels used as left and right
+    pub fn get_channels(&self) -> (usize, usize) {
+        self.data.channels.unwrap_or((0, 1))
+    }
+
+    /// Set channels used as left and right
+    pub fn set_channels(&mut self, channels: (usize, usize)) {
+        self.data.channels = Some(channels);
+        self.revision += 1;
+    }
+
+    /// Changes whenever the window changes
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Get offset
+    pub fn get_data_offset(&self) -> usize {
+        self.data.offset
+    }
+
+    /// Set offset
+    pub fn set_data_offset(&mut self, offset: usize) {
+        self.data.offset = offset;
+        self.revision += 1;
+    }
+
+    /// set len
+    pub fn set_data_len(&mut self, len: usize) {
+        self.data.len = len;
+        self.revision += 1;
+    }
+
+    /// Get data len
+    pub fn get_data_len(&self) -> usize {
+        self.data.len
+    }
+
+    /// Store undo checkpoint
+    pub fn checkpoint(&mut self) {
+        self.undo.push(self.data.clone());
+    }
+
+    /// Restore last undo checkpoint
+    pub fn undo(&mut self) {
+        if let Some(check_point) = self.undo.pop() {
+            self.redo.push(self.data.clone());
+            self.data = check_point;
+            self.revision += 1;
+        }
+    }
+
+    /// Restore last redo checkpoint
+    pub fn redo(&mut self) {
+        if let Some(check_point) = self.redo.pop() {
+            self.undo.push(self.data.clone());
+            self.data = check_point;
+            self.revision += 1;
+        }
+    }
+
+    /// Get undo len
+    pub fn get_undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Get redo len
+    pub fn get_redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Needs save
+    pub fn needs_save(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Clear undo redo buffers
+    pub fn clear_undo_redo(&mut self) {
+        self.undo = vec![];
+        self.redo = vec![];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frequency of the first channel from its rising zero crossings
+    fn frequency(wav: &WavData) -> f32 {
+        let samples = &wav.channels[0];
+        let crossings: Vec<usize> = (1..samples.len())
+            .filter(|&i| samples[i - 1] < 0.0 && samples[i] >= 0.0)
+            .collect();
+        let (first, last) = (crossings[0], *crossings.last().unwrap());
+        (crossings.len() - 1) as f32 * wav.rate as f32 / (last - first) as f32
+    }
+
+    #[test]
+    fn convert_rate() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("audio/sine.wav");
+        let mut wav = WavData::load_wav_data(path).unwrap();
+        let (from, len, freq) = (wav.rate, wav.len, frequency(&wav));
+        let to = if from == 48000 { 44100 } else { 48000 };
+
+        wav.convert_rate(to).unwrap();
+        assert_eq!(wav.get_rate(), to);
+        assert_eq!(wav.get_source_rate(), Some(from));
+        let expected = (len as f64 * to as f64 / from as f64).round() as usize;
+        assert_eq!(wav.len, expected);
+        assert!(wav.channels.iter().all(|channel| channel.len() == expected));
+        // same pitch at the new rate
+        assert!((frequency(&wav) / freq - 1.0).abs() < 1e-3);
+    }
+}
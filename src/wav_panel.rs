@@ -1,132 +1,16 @@
-use crate::config::Config;
+use crate::{
+    config::Config,
+    wav_data::{Wav, WavData},
+};
 use egui::epaint::PathShape;
 use egui::*;
-use serde::{Deserialize, Serialize};
 
-use std::path::PathBuf;
-// use wav::{BitDepth, Header};
-use hound::WavSpec;
 use log::{debug, trace};
 
-#[derive(Default, Debug)]
-pub struct WavData {
-    wav_spec: Option<WavSpec>,
-    // _stereo: Vec<f32>,
-    left: Vec<f32>,
-    right: Vec<f32>,
-    /// the max length
-    pub len: usize,
-    /// filename
-    pub filename: String,
-}
-
-impl WavData {
-    /// load
-    pub fn load_wav_data(path: PathBuf) -> Result<Self, String> {
-        let filename = path.file_stem().unwrap().to_str().unwrap().to_owned();
-        let mut reader = hound::WavReader::open(path)
-            .map_err(|_| "Unsupported format (use stereo WAV)".to_string())?;
-        let wav_spec = reader.spec();
-        trace!("wav_spec {:?}", wav_spec);
-        if wav_spec.channels != 2 {
-            Err("Only stereo files supported")?;
-        }
-
-        let (left, right, _b) =
-            reader
-                .samples::<f32>()
-                .fold((vec![], vec![], false), |(mut l, mut r, b), s| {
-                    let s = s.unwrap();
-                    if b {
-                        l.push(s);
-                    } else {
-                        r.push(s);
-                    }
-                    (l, r, !b)
-                });
-
-        let len = reader.duration() as usize;
-        trace!("duration: len samples {}", len);
-
-        Ok(WavData {
-            wav_spec: Some(wav_spec),
-            // _stereo,
-            left,
-            right,
-            len,
-            filename,
-        })
-    }
-
-    pub fn get_wav_spec(&self) -> Option<WavSpec> {
-        self.wav_spec
-    }
-
-    pub fn get_sample(&self, index: usize) -> (f32, f32) {
-        (
-            *self.left.get(index).unwrap_or(&0.0),
-            *self.right.get(index).unwrap_or(&0.0),
-        )
-    }
-}
-
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-struct CheckPointData {
-    offset: usize, // in samples, wrapping
-    len: usize,    // in samples
-}
-#[derive(Serialize, Deserialize, Debug, Default)]
-pub struct Wav {
-    data: CheckPointData,
-
-    #[serde(skip)]
-    undo: Vec<CheckPointData>,
-    redo: Vec<CheckPointData>,
-}
+/// Shortest window when zooming, in samples, unless the sample is shorter
+const MIN_LEN: usize = 10_000;
 
 impl Wav {
-    /// get_sample at v, where p = 0..data.len
-    pub fn get_sample(&self, p: usize, wav_data: &WavData) -> (f32, f32) {
-        let t = p + self.data.offset;
-        wav_data.get_sample(t)
-    }
-
-    /// Get offset
-    pub fn get_data_offset(&self) -> usize {
-        self.data.offset
-    }
-
-    /// set len
-    pub fn set_data_len(&mut self, len: usize) {
-        self.data.len = len
-    }
-
-    /// Get data len
-    pub fn get_data_len(&self) -> usize {
-        self.data.len
-    }
-
-    /// Get undo len
-    pub fn get_undo_len(&self) -> usize {
-        self.undo.len()
-    }
-
-    /// Get redo len
-    pub fn get_redo_len(&self) -> usize {
-        self.redo.len()
-    }
-
-    /// Needs save
-    pub fn needs_save(&self) -> bool {
-        !self.undo.is_empty()
-    }
-
-    /// Clear undo redo buffers
-    pub fn clear_undo_redo(&mut self) {
-        self.undo = vec![];
-        self.redo = vec![];
-    }
-
     /// Control panel for the Wav
     pub fn ui_content_ctrl(&mut self, ui: &mut Ui, wav_data: &WavData, i: usize) {
         ui.label(format!("#{}: {}", i, wav_data.filename));
@@ -134,25 +18,49 @@ impl Wav {
 
         ui.horizontal(|ui| {
             if ui.button("X").clicked() {
-                self.data.offset = 0;
+                self.set_data_offset(0);
             }
-            ui.label(format!("offset {}", self.data.offset));
+            ui.label(format!("offset {}", self.get_data_offset()));
         });
 
         ui.horizontal(|ui| {
             if ui.button("X").clicked() {
-                self.data.len = wav_data.len
+                self.set_data_len(wav_data.len);
             }
-            ui.label(format!("len {}", self.data.len));
+            ui.label(format!("len {}", self.get_data_len()));
         });
+
+        // multichannel, select the pair used as left and right
+        if wav_data.nr_channels() > 2 {
+            let (mut left, mut right) = self.get_channels();
+            ui.horizontal(|ui| {
+                ui.label("channels");
+                for (name, channel) in [("left", &mut left), ("right", &mut right)] {
+                    ComboBox::from_id_source(("channel", name))
+                        .width(40.0)
+                        .selected_text(format!("{}", *channel + 1))
+                        .show_ui(ui, |ui| {
+                            for c in 0..wav_data.nr_channels() {
+                                ui.selectable_value(channel, c, format!("{}", c + 1));
+                            }
+                        });
+                }
+            });
+            if (left, right) != self.get_channels() {
+                self.checkpoint();
+                self.set_channels((left, right));
+            }
+        }
     }
 
-    /// main panel
+    /// main panel, playhead is the value played
+    #[allow(clippy::too_many_arguments)]
     pub fn ui_content(
         &mut self,
         ui: &mut Ui,
         cut_cursor: Option<Pos2>,
         cut_value: Option<f32>,
+        playhead: Option<f32>,
         wav_data: &WavData,
         config: &Config,
         height: f32,
@@ -170,70 +78,84 @@ impl Wav {
         let width = response.rect.width();
         let height = response.rect.height();
 
+        // nothing to show, or edit, without a sample
+        if wav_data.len == 0 {
+            return response;
+        }
+
         // length
         if response.drag_started_by(PointerButton::Secondary) {
             debug!("length drag_started_by");
-            self.undo.push(self.data.clone());
+            self.checkpoint();
         }
 
         // length
         if response.dragged_by(PointerButton::Secondary) {
             trace!("length dragged_by");
             let delta = response.drag_delta();
-            let delta_scale = ((delta.y / height) * self.data.len as f32) as i32 as usize;
+            let len = self.get_data_len();
+            let delta_scale = ((delta.y / height) * len as f32) as i32 as usize;
 
-            self.data.len = (self.data.len - delta_scale).max(10_000).min(wav_data.len);
-            assert!(self.data.len >= 10_000 && self.data.len <= wav_data.len);
+            let min_len = MIN_LEN.min(wav_data.len);
+            self.set_data_len((len - delta_scale).clamp(min_len, wav_data.len));
+            assert!(self.get_data_len() >= min_len && self.get_data_len() <= wav_data.len);
         }
 
         // offset
         if response.drag_started_by(PointerButton::Primary) {
             debug!("offset drag_started_by");
-            self.undo.push(self.data.clone());
+            self.checkpoint();
         }
         // offset
         if response.dragged_by(PointerButton::Primary) {
             trace!("offset dragged_by");
             let delta = response.drag_delta();
 
-            let delta_scale = ((delta.y / height) * self.data.len as f32) as i32 as usize;
+            let delta_scale = ((delta.y / height) * self.get_data_len() as f32) as i32 as usize;
 
-            self.data.offset = (wav_data.len + self.data.offset - delta_scale) % wav_data.len;
-            assert!(self.data.offset <= wav_data.len);
+            self.set_data_offset(
+                (wav_data.len + self.get_data_offset() - delta_scale) % wav_data.len,
+            );
+            assert!(self.get_data_offset() <= wav_data.len);
         }
 
         if response.hovered() {
             // undo checkpoint
             if ui.input_mut(|i| i.consume_key(Modifiers::CTRL, Key::Z)) {
                 debug!("Ctrl-Z");
-                if let Some(check_point) = self.undo.pop() {
-                    self.redo.push(self.data.clone());
-                    self.data = check_point;
-                }
+                self.undo();
             }
 
             // redo checkpoint
             if ui.input_mut(|i| i.consume_key(Modifiers::CTRL | Modifiers::SHIFT, Key::Z)) {
                 debug!("SHIFT Ctrl-Z");
-                if let Some(check_point) = self.redo.pop() {
-                    self.undo.push(self.data.clone());
-                    self.data = check_point;
-                }
+                self.redo();
             }
         }
 
-        // compute left/right sample
+        // compute left/right sample, mono is drawn as a single centered trace
+        let mono = wav_data.is_mono();
         let mut left: Vec<Pos2> = vec![];
         let mut right: Vec<Pos2> = vec![];
 
-        let step = self.data.len as f32 / height;
+        let step = self.get_data_len() as f32 / height;
 
         for i in 0..height as usize {
-            let t = (((i as f32) * step) as usize + self.data.offset + wav_data.len) % wav_data.len;
+            let t = (((i as f32) * step) as usize + self.get_data_offset() + wav_data.len)
+                % wav_data.len;
             assert!(t <= wav_data.len);
 
-            let l: f32 = wav_data.left[t];
-            let r: f32 = wav_data.right[t];
+            let (l, r) = wav_data.get_sample(t, self.get_channels());
+            if mono {
+                left.push(
+                    to_screen
+                        * Pos2 {
+                            x: l * width * 0.5 + width * 0.5,
+                            y: i as f32,
+                        },
+                );
+                continue;
+            }
             left.push(
                 to_screen
                     * Pos2 {
@@ -291,6 +213,25 @@ impl Wav {
             ));
         }
 
+        // paint playhead
+        if let Some(value) = playhead {
+            painter.add(PathShape::line(
+                vec![
+                    to_screen
+                        * Pos2 {
+                            x: 0.0,
+                            y: value * height,
+                        },
+                    to_screen
+                        * Pos2 {
+                            x: width,
+                            y: value * height,
+                        },
+                ],
+                config.stroke_playhead,
+            ));
+        }
+
         response
     }
 }